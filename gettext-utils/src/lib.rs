@@ -1,61 +1,857 @@
+pub mod locale;
+pub mod po;
+
+pub use locale::{env_languages, parse_accept_language, LanguageTag};
+
+/// An error that occured while formatting a (translated) message.
 #[derive(Debug)]
-#[doc(hidden)]
 pub enum FormatError {
     UnmatchedCurlyBracket,
     InvalidPositionalArgument,
+    /// The destination of `write_format` returned an error.
+    Write(::std::fmt::Error),
+}
+
+impl ::std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            FormatError::UnmatchedCurlyBracket => f.write_str("unmatched curly bracket"),
+            FormatError::InvalidPositionalArgument => f.write_str("invalid positional argument"),
+            FormatError::Write(_) => f.write_str("couldn't write the formatted message"),
+        }
+    }
+}
+
+impl ::std::error::Error for FormatError {}
+
+/// Iterates over the parts of a pattern: each one is some text, optionally followed
+/// by the index of the argument to insert after it.
+struct Segments<'a> {
+    rest: ::std::option::Option<&'a str>,
+    index: usize,
+}
+
+impl<'a> Segments<'a> {
+    fn new(pattern: &'a str) -> Segments<'a> {
+        Segments {
+            rest: ::std::option::Option::Some(pattern),
+            index: 0,
+        }
+    }
+}
+
+impl<'a> ::std::iter::Iterator for Segments<'a> {
+    type Item = ::std::result::Result<(&'a str, ::std::option::Option<usize>), FormatError>;
+
+    fn next(&mut self) -> ::std::option::Option<Self::Item> {
+        let rest = self.rest?;
+        let (part, last) = match rest.find('}') {
+            ::std::option::Option::Some(end) => {
+                self.rest = ::std::option::Option::Some(&rest[end + 1..]);
+                (&rest[..end], false)
+            }
+            ::std::option::Option::None => {
+                self.rest = ::std::option::Option::None;
+                (rest, true)
+            }
+        };
+        let i = self.index;
+        self.index += 1;
+
+        let segment = match part.find('{') {
+            ::std::option::Option::Some(start) => {
+                let arg = &part[start + 1..];
+                if arg.contains('{') {
+                    ::std::result::Result::Err(FormatError::UnmatchedCurlyBracket)
+                } else if arg.is_empty() {
+                    ::std::result::Result::Ok((&part[..start], ::std::option::Option::Some(i)))
+                } else {
+                    arg.parse()
+                        .map(|arg| (&part[..start], ::std::option::Option::Some(arg)))
+                        .map_err(|_| FormatError::InvalidPositionalArgument)
+                }
+            }
+            ::std::option::Option::None if last => {
+                ::std::result::Result::Ok((part, ::std::option::Option::None))
+            }
+            ::std::option::Option::None => {
+                ::std::result::Result::Err(FormatError::UnmatchedCurlyBracket)
+            }
+        };
+        if segment.is_err() {
+            self.rest = ::std::option::Option::None;
+        }
+        ::std::option::Option::Some(segment)
+    }
+}
+
+/// A pattern that has already been parsed, and can be formatted many times.
+pub struct Pattern<'a> {
+    segments: ::std::vec::Vec<(&'a str, ::std::option::Option<usize>)>,
+    argc: usize,
+}
+
+impl<'a> Pattern<'a> {
+    /// Parses a pattern.
+    pub fn parse(pattern: &'a str) -> ::std::result::Result<Pattern<'a>, FormatError> {
+        let segments =
+            Segments::new(pattern).collect::<::std::result::Result<::std::vec::Vec<_>, _>>()?;
+        let argc = segments
+            .iter()
+            .filter_map(|(_, arg)| arg.map(|a| a + 1))
+            .max()
+            .unwrap_or(0);
+        ::std::result::Result::Ok(Pattern { segments, argc })
+    }
+
+    /// The number of format arguments this pattern needs.
+    pub fn argc(&self) -> usize {
+        self.argc
+    }
+
+    /// Formats this pattern with the given arguments, and writes the result to `out`.
+    ///
+    /// Nothing is written if there are not enough arguments.
+    pub fn write_format<W: ::std::fmt::Write + ?Sized, D: ::std::fmt::Display>(
+        &self,
+        out: &mut W,
+        argv: &[D],
+    ) -> ::std::result::Result<(), FormatError> {
+        let formatted = Formatted::parsed(self, argv)?;
+        out.write_fmt(format_args!("{}", formatted))
+            .map_err(FormatError::Write)
+    }
+}
+
+static PATTERNS: ::std::sync::OnceLock<
+    ::std::sync::RwLock<::std::collections::HashMap<(usize, usize), &'static Pattern<'static>>>,
+> = ::std::sync::OnceLock::new();
+
+/// Gives the parsed version of a `'static` pattern, parsing it only the first time.
+///
+/// The cache is keyed by the address of the string, which makes lookups cheap. It is meant
+/// to be used with the strings of a catalog that lives as long as the program, since parsed
+/// patterns are never freed.
+pub fn cached_pattern(
+    pattern: &'static str,
+) -> ::std::result::Result<&'static Pattern<'static>, FormatError> {
+    let key = (pattern.as_ptr() as usize, pattern.len());
+    let cache = PATTERNS.get_or_init(::std::default::Default::default);
+    if let ::std::option::Option::Some(parsed) =
+        cache.read().unwrap_or_else(|e| e.into_inner()).get(&key)
+    {
+        return ::std::result::Result::Ok(parsed);
+    }
+
+    let parsed = Pattern::parse(pattern)?;
+    let mut cache = cache.write().unwrap_or_else(|e| e.into_inner());
+    ::std::result::Result::Ok(
+        cache
+            .entry(key)
+            .or_insert_with(|| ::std::boxed::Box::leak(::std::boxed::Box::new(parsed))),
+    )
+}
+
+enum Source<'a> {
+    Raw(&'a str),
+    Parsed(&'a Pattern<'a>),
+}
+
+/// A message, checked against its format arguments, that is formatted when displayed.
+///
+/// Nothing is allocated to display it.
+pub struct Formatted<'a, D: ::std::fmt::Display> {
+    pattern: Source<'a>,
+    argv: &'a [D],
+}
+
+impl<'a, D: ::std::fmt::Display> Formatted<'a, D> {
+    /// Checks that `pattern` is valid, and that `argv` contains all the arguments it uses.
+    pub fn new(pattern: &'a str, argv: &'a [D]) -> ::std::result::Result<Self, FormatError> {
+        for segment in Segments::new(pattern) {
+            if let (_, ::std::option::Option::Some(arg)) = segment? {
+                if arg >= argv.len() {
+                    return ::std::result::Result::Err(FormatError::InvalidPositionalArgument);
+                }
+            }
+        }
+        ::std::result::Result::Ok(Formatted {
+            pattern: Source::Raw(pattern),
+            argv,
+        })
+    }
+
+    /// Checks that `argv` contains all the arguments used by an already parsed pattern.
+    pub fn parsed(
+        pattern: &'a Pattern<'a>,
+        argv: &'a [D],
+    ) -> ::std::result::Result<Self, FormatError> {
+        if pattern.argc > argv.len() {
+            return ::std::result::Result::Err(FormatError::InvalidPositionalArgument);
+        }
+        ::std::result::Result::Ok(Formatted {
+            pattern: Source::Parsed(pattern),
+            argv,
+        })
+    }
+
+    /// Same as `Formatted::new`, but the pattern is only parsed once, with `cached_pattern`.
+    pub fn cached(
+        pattern: &'static str,
+        argv: &'a [D],
+    ) -> ::std::result::Result<Self, FormatError> {
+        Formatted::parsed(cached_pattern(pattern)?, argv)
+    }
+
+    /// Same as `Formatted::new_or_fallback`, but the translation is only parsed once, with `cached_pattern`.
+    pub fn cached_or_fallback(translation: &'static str, msgid: &'a str, argv: &'a [D]) -> Self {
+        Formatted::cached(translation, argv).unwrap_or_else(|err| {
+            report_format_error(msgid, translation, &err);
+            Formatted::new(msgid, argv).expect("Error while formatting message")
+        })
+    }
+
+    /// Same as `Formatted::new`, but uses `msgid` as the pattern if `translation` is invalid.
+    ///
+    /// The error is reported to the hook registered with `set_error_hook`.
+    ///
+    /// # Panics
+    ///
+    /// If `msgid` is not valid either.
+    pub fn new_or_fallback(translation: &'a str, msgid: &'a str, argv: &'a [D]) -> Self {
+        Formatted::new(translation, argv).unwrap_or_else(|err| {
+            report_format_error(msgid, translation, &err);
+            Formatted::new(msgid, argv).expect("Error while formatting message")
+        })
+    }
+}
+
+impl<'a, D: ::std::fmt::Display> ::std::fmt::Display for Formatted<'a, D> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let write_segment = |(text, arg): (&str, ::std::option::Option<usize>)| {
+            f.write_str(text)?;
+            if let ::std::option::Option::Some(arg) = arg {
+                self.argv[arg].fmt(f)?;
+            }
+            ::std::result::Result::Ok(())
+        };
+        // the pattern has been checked when creating self
+        match self.pattern {
+            Source::Raw(pattern) => Segments::new(pattern).flatten().try_for_each(write_segment),
+            Source::Parsed(pattern) => pattern.segments.iter().cloned().try_for_each(write_segment),
+        }
+    }
+}
+
+/// Formats `str_pattern` with the given arguments, and writes the result to `out`.
+///
+/// Arguments are usually `&dyn Display` references, so that nothing needs to be allocated.
+/// Nothing is written if the pattern is invalid.
+pub fn write_format<W: ::std::fmt::Write + ?Sized, D: ::std::fmt::Display>(
+    out: &mut W,
+    str_pattern: &str,
+    argv: &[D],
+) -> ::std::result::Result<(), FormatError> {
+    let formatted = Formatted::new(str_pattern, argv)?;
+    out.write_fmt(format_args!("{}", formatted))
+        .map_err(FormatError::Write)
 }
 
 #[doc(hidden)]
-pub fn try_format<'a>(
-    str_pattern: &'a str,
-    argv: &[::std::boxed::Box<dyn ::std::fmt::Display + 'a>],
+pub fn try_format<D: ::std::fmt::Display>(
+    str_pattern: &str,
+    argv: &[D],
 ) -> ::std::result::Result<::std::string::String, FormatError> {
-    use ::std::fmt::Write;
-
-    //first we parse the pattern
-    let mut pattern = vec![];
-    let mut vars = vec![];
-    let mut finish_or_fail = false;
-    for (i, part) in str_pattern.split('}').enumerate() {
-        if finish_or_fail {
-            return ::std::result::Result::Err(FormatError::UnmatchedCurlyBracket);
-        }
-        if part.contains('{') {
-            let mut part = part.split('{');
-            let text = part.next().unwrap();
-            let arg = part.next().ok_or(FormatError::UnmatchedCurlyBracket)?;
-            if part.next() != ::std::option::Option::None {
-                return ::std::result::Result::Err(FormatError::UnmatchedCurlyBracket);
-            }
-            pattern.push(text);
-            vars.push(
-                argv.get::<usize>(if arg.len() > 0 {
-                    arg.parse()
-                        .map_err(|_| FormatError::InvalidPositionalArgument)?
-                } else {
-                    i
-                })
-                .ok_or(FormatError::InvalidPositionalArgument)?,
-            );
+    let mut res = ::std::string::String::with_capacity(str_pattern.len());
+    write_format(&mut res, str_pattern, argv)?;
+    ::std::result::Result::Ok(res)
+}
+
+/// An error that the program can recover from, and that is reported to the hook registered with `set_error_hook`.
+#[derive(Debug)]
+pub enum TranslationError<'a> {
+    /// A translation couldn't be formatted, and the original message was used instead
+    /// (when `fallback = true` is given to `init_i18n!`).
+    Format {
+        msgid: &'a str,
+        translation: &'a str,
+        error: &'a FormatError,
+    },
+    /// A catalog couldn't be loaded from its `.po` file (when `reload = true` is given to `init_i18n!`).
+    /// Its previous version is used, or an empty catalog if it was never loaded.
+    Load { path: &'a str, error: &'a str },
+}
+
+impl<'a> ::std::fmt::Display for TranslationError<'a> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            TranslationError::Format {
+                msgid,
+                translation,
+                error,
+            } => write!(
+                f,
+                "couldn't format the translation of {:?} ({:?}): {}",
+                msgid, translation, error
+            ),
+            TranslationError::Load { path, error } => {
+                write!(f, "couldn't load the catalog at {}: {}", path, error)
+            }
+        }
+    }
+}
+
+/// A function called when a `TranslationError` happens.
+pub type ErrorHook = fn(&TranslationError);
+
+static ERROR_HOOK: ::std::sync::RwLock<::std::option::Option<ErrorHook>> =
+    ::std::sync::RwLock::new(::std::option::Option::None);
+
+/// Registers the function to call when a translation can't be formatted or a catalog can't be loaded,
+/// and the program goes on without it.
+///
+/// By default, these errors are silently ignored.
+pub fn set_error_hook(hook: ErrorHook) {
+    *ERROR_HOOK.write().unwrap_or_else(|e| e.into_inner()) = ::std::option::Option::Some(hook);
+}
+
+fn report_error(err: &TranslationError) {
+    let hook = *ERROR_HOOK.read().unwrap_or_else(|e| e.into_inner());
+    if let ::std::option::Option::Some(hook) = hook {
+        hook(err);
+    }
+}
+
+fn report_format_error(msgid: &str, translation: &str, error: &FormatError) {
+    report_error(&TranslationError::Format {
+        msgid,
+        translation,
+        error,
+    });
+}
+
+#[doc(hidden)]
+pub fn try_format_or_fallback<D: ::std::fmt::Display>(
+    translation: &str,
+    msgid: &str,
+    argv: &[D],
+) -> ::std::string::String {
+    Formatted::new_or_fallback(translation, msgid, argv).to_string()
+}
+
+/// An integer that can be used to choose between the singular and plural forms of a message.
+///
+/// Negative numbers are replaced by their absolute value, and numbers that are too big are capped
+/// to `u64::MAX`.
+pub trait PluralCount {
+    fn plural_count(self) -> u64;
+}
+
+macro_rules! impl_plural_count {
+    (unsigned: $($t:ty),*; signed: $($s:ty),*) => {
+        $(
+            impl PluralCount for $t {
+                fn plural_count(self) -> u64 {
+                    ::std::convert::TryFrom::try_from(self).unwrap_or(u64::MAX)
+                }
+            }
+        )*
+        $(
+            impl PluralCount for $s {
+                fn plural_count(self) -> u64 {
+                    ::std::convert::TryFrom::try_from(self.unsigned_abs()).unwrap_or(u64::MAX)
+                }
+            }
+        )*
+    };
+}
+
+impl_plural_count!(unsigned: u8, u16, u32, u64, u128, usize; signed: i8, i16, i32, i64, i128, isize);
+
+impl<T: PluralCount + Copy> PluralCount for &T {
+    fn plural_count(self) -> u64 {
+        (*self).plural_count()
+    }
+}
+
+#[doc(hidden)]
+pub fn plural_count<N: PluralCount>(n: N) -> u64 {
+    n.plural_count()
+}
+
+/// A translated message, that is only looked up in its catalog and formatted when displayed.
+///
+/// It borrows the catalog and the format arguments. This is what `i18n_lazy!` returns.
+pub struct LazyMessage<'a, C: ?Sized, F, const N: usize> {
+    catalog: &'a C,
+    translate: F,
+    argv: [&'a dyn ::std::fmt::Display; N],
+    fallback: bool,
+}
+
+impl<'a, C, F, const N: usize> LazyMessage<'a, C, F, N>
+where
+    C: ?Sized,
+    F: Fn(&'a C) -> (&'a str, &'a str),
+{
+    /// Creates a new lazy message.
+    ///
+    /// `translate` should return the translation of the message in the catalog, and the original message.
+    /// If `fallback` is `true`, the original message is used when the translation can't be formatted,
+    /// as `Formatted::new_or_fallback` does.
+    pub fn new(
+        catalog: &'a C,
+        translate: F,
+        argv: [&'a dyn ::std::fmt::Display; N],
+        fallback: bool,
+    ) -> Self {
+        LazyMessage {
+            catalog,
+            translate,
+            argv,
+            fallback,
+        }
+    }
+}
+
+impl<'a, C, F, const N: usize> ::std::fmt::Display for LazyMessage<'a, C, F, N>
+where
+    C: ?Sized,
+    F: Fn(&'a C) -> (&'a str, &'a str),
+{
+    /// Displays the formatted translation.
+    ///
+    /// Unless `fallback` is enabled, an error is returned if the translation can't be formatted.
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let (translation, msgid) = (self.translate)(self.catalog);
+        let formatted = if self.fallback {
+            Formatted::new_or_fallback(translation, msgid, &self.argv[..])
         } else {
-            finish_or_fail = true;
-            pattern.push(part);
+            Formatted::new(translation, &self.argv[..]).map_err(|_| ::std::fmt::Error)?
+        };
+        formatted.fmt(f)
+    }
+}
+
+/// A catalog that is only parsed the first time it is used.
+///
+/// It is usually embedded in the binary, but in development mode it can also be loaded from a
+/// `.po` file at runtime, and reloaded when this file changes.
+///
+/// It dereferences to the parsed catalog, so it can be used directly with `i18n!`.
+pub struct LazyCatalog<C: 'static> {
+    source: CatalogSource,
+    parse: fn(&[u8]) -> C,
+    catalog: ::std::sync::OnceLock<C>,
+    loaded: ::std::sync::Mutex<::std::option::Option<LoadedCatalog<C>>>,
+}
+
+enum CatalogSource {
+    Embedded(&'static [u8]),
+    #[cfg(feature = "compress")]
+    Compressed(&'static [u8]),
+    Po(&'static str),
+}
+
+struct LoadedCatalog<C: 'static> {
+    modified: ::std::option::Option<::std::time::SystemTime>,
+    checked: ::std::time::Instant,
+    catalog: &'static C,
+}
+
+/// How often a catalog loaded from a file checks if this file changed.
+const RELOAD_INTERVAL: ::std::time::Duration = ::std::time::Duration::from_secs(1);
+
+impl<C> LazyCatalog<C> {
+    /// Creates a new lazy catalog, that will be parsed from `data` with the `parse` function.
+    pub const fn new(data: &'static [u8], parse: fn(&[u8]) -> C) -> LazyCatalog<C> {
+        LazyCatalog {
+            source: CatalogSource::Embedded(data),
+            parse,
+            catalog: ::std::sync::OnceLock::new(),
+            loaded: ::std::sync::Mutex::new(::std::option::Option::None),
         }
     }
 
-    //then we generate the result String
-    let mut res = ::std::string::String::with_capacity(str_pattern.len());
-    let mut pattern = pattern.iter();
-    let mut vars = vars.iter();
-    while let ::std::option::Option::Some(text) = pattern.next() {
-        res.write_str(text).unwrap();
-        if let ::std::option::Option::Some(var) = vars.next() {
-            res.write_str(&format!("{}", var)).unwrap();
+    /// Creates a new lazy catalog, from `data` compressed with DEFLATE.
+    ///
+    /// It will be decompressed and parsed with the `parse` function the first time it is used.
+    #[cfg(feature = "compress")]
+    pub const fn compressed(data: &'static [u8], parse: fn(&[u8]) -> C) -> LazyCatalog<C> {
+        LazyCatalog {
+            source: CatalogSource::Compressed(data),
+            parse,
+            catalog: ::std::sync::OnceLock::new(),
+            loaded: ::std::sync::Mutex::new(::std::option::Option::None),
         }
     }
-    ::std::result::Result::Ok(res)
+
+    /// Creates a new lazy catalog, that will be loaded from the `.po` file at `path`.
+    ///
+    /// The file is converted to the `.mo` format before being given to `parse`. It is loaded again
+    /// when it changes, and as the previous catalogs may still be borrowed, they are leaked: this
+    /// should only be used during development.
+    pub const fn from_po_file(path: &'static str, parse: fn(&[u8]) -> C) -> LazyCatalog<C> {
+        LazyCatalog {
+            source: CatalogSource::Po(path),
+            parse,
+            catalog: ::std::sync::OnceLock::new(),
+            loaded: ::std::sync::Mutex::new(::std::option::Option::None),
+        }
+    }
+
+    /// Gives the catalog, parsing it if it is the first time it is used (or if its file changed).
+    ///
+    /// If the catalog is loaded from a file that can't be read or parsed, the error is reported to the hook
+    /// registered with `set_error_hook`, and the previous version of the catalog is kept (or an empty catalog
+    /// is used, if it was never loaded).
+    pub fn get(&self) -> &C {
+        match self.source {
+            CatalogSource::Embedded(data) => self.catalog.get_or_init(|| (self.parse)(data)),
+            #[cfg(feature = "compress")]
+            CatalogSource::Compressed(data) => self.catalog.get_or_init(|| {
+                let data = ::miniz_oxide::inflate::decompress_to_vec(data)
+                    .expect("Couldn't decompress catalog");
+                (self.parse)(&data)
+            }),
+            CatalogSource::Po(path) => self.reload(path),
+        }
+    }
+
+    fn reload(&self, path: &str) -> &'static C {
+        let mut loaded = self
+            .loaded
+            .lock()
+            .unwrap_or_else(::std::sync::PoisonError::into_inner);
+        let now = ::std::time::Instant::now();
+        let modified = match *loaded {
+            ::std::option::Option::Some(ref l)
+                if now.duration_since(l.checked) < RELOAD_INTERVAL =>
+            {
+                return l.catalog
+            }
+            _ => ::std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+        };
+        if let ::std::option::Option::Some(ref mut l) = *loaded {
+            l.checked = now;
+            if l.modified == modified {
+                return l.catalog;
+            }
+        }
+
+        let catalog = ::std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| po::Po::parse(&content))
+            .map(|po| (self.parse)(&po.to_mo()));
+        let (catalog, error) = match (catalog, loaded.as_ref()) {
+            (::std::result::Result::Ok(catalog), _) => {
+                let catalog: &'static C = ::std::boxed::Box::leak(::std::boxed::Box::new(catalog));
+                (catalog, ::std::option::Option::None)
+            }
+            (::std::result::Result::Err(error), ::std::option::Option::Some(previous)) => {
+                (previous.catalog, ::std::option::Option::Some(error))
+            }
+            (::std::result::Result::Err(error), ::std::option::Option::None) => {
+                let empty = po::Po {
+                    entries: vec![],
+                    header: ::std::option::Option::None,
+                    plural: ::std::option::Option::None,
+                };
+                let catalog: &'static C =
+                    ::std::boxed::Box::leak(::std::boxed::Box::new((self.parse)(&empty.to_mo())));
+                (catalog, ::std::option::Option::Some(error))
+            }
+        };
+        // a file that couldn't be loaded is only tried again when it changes
+        *loaded = ::std::option::Option::Some(LoadedCatalog {
+            modified,
+            checked: now,
+            catalog,
+        });
+        // the hook may use this catalog too, so it is called once it is unlocked
+        ::std::mem::drop(loaded);
+        if let ::std::option::Option::Some(error) = error {
+            report_error(&TranslationError::Load {
+                path,
+                error: &error,
+            });
+        }
+        catalog
+    }
+}
+
+impl<C> ::std::ops::Deref for LazyCatalog<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.get()
+    }
+}
+
+/// A translated message of a `StaticCatalog`: its context, the original message,
+/// and its translations (one for each plural form).
+pub type StaticMessage = (
+    ::std::option::Option<&'static str>,
+    &'static str,
+    &'static [&'static str],
+);
+
+/// A catalog that was compiled to Rust code, and that doesn't need to be parsed at runtime.
+///
+/// This is what `include_i18n!` gives when `compiled = true` is passed to `init_i18n!`. It has
+/// the same methods as `gettext::Catalog` to translate messages, so it can be used with `i18n!`.
+pub struct StaticCatalog {
+    messages: &'static [StaticMessage],
+    plural: fn(u64) -> usize,
+}
+
+impl StaticCatalog {
+    /// Creates a new catalog.
+    ///
+    /// `messages` should be sorted by context and original message, and `plural` gives the index of
+    /// the plural form to use for a given count.
+    pub const fn new(
+        messages: &'static [StaticMessage],
+        plural: fn(u64) -> usize,
+    ) -> StaticCatalog {
+        StaticCatalog { messages, plural }
+    }
+
+    fn find(
+        &self,
+        context: ::std::option::Option<&str>,
+        msg_id: &str,
+    ) -> ::std::option::Option<&'static [&'static str]> {
+        self.messages
+            .binary_search_by(|(c, id, _)| (*c, *id).cmp(&(context, msg_id)))
+            .ok()
+            .map(|i| self.messages[i].2)
+    }
+
+    fn translate<'a>(
+        &'a self,
+        context: ::std::option::Option<&str>,
+        msg_id: &'a str,
+        msg_id_plural: &'a str,
+        n: u64,
+    ) -> &'a str {
+        let form = (self.plural)(n);
+        self.find(context, msg_id)
+            .and_then(|translations| translations.get(form))
+            .filter(|translation| !translation.is_empty())
+            .copied()
+            .unwrap_or(if n == 1 { msg_id } else { msg_id_plural })
+    }
+
+    /// Translates a message.
+    pub fn gettext<'a>(&'a self, msg_id: &'a str) -> &'a str {
+        self.translate(::std::option::Option::None, msg_id, msg_id, 1)
+    }
+
+    /// Translates a message that has a plural form.
+    pub fn ngettext<'a>(&'a self, msg_id: &'a str, msg_id_plural: &'a str, n: u64) -> &'a str {
+        self.translate(::std::option::Option::None, msg_id, msg_id_plural, n)
+    }
+
+    /// Translates a message in a given context.
+    pub fn pgettext<'a>(&'a self, msg_context: &'a str, msg_id: &'a str) -> &'a str {
+        self.translate(::std::option::Option::Some(msg_context), msg_id, msg_id, 1)
+    }
+
+    /// Translates a message that has a plural form, in a given context.
+    pub fn npgettext<'a>(
+        &'a self,
+        msg_context: &'a str,
+        msg_id: &'a str,
+        msg_id_plural: &'a str,
+        n: u64,
+    ) -> &'a str {
+        self.translate(
+            ::std::option::Option::Some(msg_context),
+            msg_id,
+            msg_id_plural,
+            n,
+        )
+    }
+}
+
+/// The translation catalogs of a crate, for each of its languages.
+///
+/// This is what `include_i18n!` returns. The catalogs are stored in the order in which
+/// the languages were given to `init_i18n!`, and this type dereferences to a slice of
+/// `(language, catalog)` pairs. The default language is the first one.
+pub struct Catalogs<C> {
+    catalogs: ::std::vec::Vec<(&'static str, C)>,
+    index: ::std::collections::HashMap<&'static str, usize>,
+    tags: ::std::vec::Vec<::std::option::Option<LanguageTag>>,
 }
 
+impl<C> Catalogs<C> {
+    /// Creates a new set of catalogs. The first language is the default one.
+    pub fn new(catalogs: ::std::vec::Vec<(&'static str, C)>) -> Catalogs<C> {
+        let index = catalogs
+            .iter()
+            .enumerate()
+            .map(|(i, (lang, _))| (*lang, i))
+            .collect();
+        let tags = catalogs
+            .iter()
+            .map(|(lang, _)| LanguageTag::parse(lang))
+            .collect();
+        Catalogs {
+            catalogs,
+            index,
+            tags,
+        }
+    }
+
+    /// Gives the catalog for a language, if it is available.
+    ///
+    /// The language can be written as a BCP 47 tag, or as a POSIX locale (`pt-BR` and `pt_BR` are the same).
+    pub fn get(&self, lang: &str) -> ::std::option::Option<&C> {
+        self.index
+            .get(lang)
+            .cloned()
+            .or_else(|| LanguageTag::parse(lang).and_then(|tag| self.find_tag(&tag)))
+            .map(|i| &self.catalogs[i].1)
+    }
+
+    /// Gives the catalog for a language, or the one of the default language if it is not available.
+    ///
+    /// `None` is only returned if there are no catalogs at all.
+    pub fn get_or_default(&self, lang: &str) -> ::std::option::Option<&C> {
+        self.get(lang).or_else(|| self.default_catalog())
+    }
+
+    /// The list of the available languages.
+    pub fn languages(&self) -> impl ::std::iter::Iterator<Item = &'static str> + '_ {
+        self.catalogs.iter().map(|(lang, _)| *lang)
+    }
+
+    /// The default language, if there is at least one language.
+    pub fn default_language(&self) -> ::std::option::Option<&'static str> {
+        self.catalogs.first().map(|(lang, _)| *lang)
+    }
+
+    /// The catalog of the default language, if there is at least one language.
+    pub fn default_catalog(&self) -> ::std::option::Option<&C> {
+        self.catalogs.first().map(|(_, catalog)| catalog)
+    }
+}
+
+impl<C> ::std::ops::Deref for Catalogs<C> {
+    type Target = [(&'static str, C)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.catalogs
+    }
+}
+
+impl<C> ::std::iter::FromIterator<(&'static str, C)> for Catalogs<C> {
+    fn from_iter<I: ::std::iter::IntoIterator<Item = (&'static str, C)>>(iter: I) -> Self {
+        Catalogs::new(iter.into_iter().collect())
+    }
+}
+
+impl<C> ::std::iter::IntoIterator for Catalogs<C> {
+    type Item = (&'static str, C);
+    type IntoIter = ::std::vec::IntoIter<(&'static str, C)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.catalogs.into_iter()
+    }
+}
+
+impl<'a, C> ::std::iter::IntoIterator for &'a Catalogs<C> {
+    type Item = &'a (&'static str, C);
+    type IntoIter = ::std::slice::Iter<'a, (&'static str, C)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.catalogs.iter()
+    }
+}
+
+/// The translation catalogs of several domains, for each of their languages.
+///
+/// This is what `include_i18n!` returns when it is given a list of crates or domains, for instance to
+/// embed the translations of all the crates of a workspace in a binary. The domains are stored in the
+/// order in which they were given to `include_i18n!`.
+pub struct Domains<C> {
+    domains: ::std::vec::Vec<(&'static str, Catalogs<C>)>,
+}
+
+impl<C> Domains<C> {
+    /// Creates a new set of domains.
+    pub fn new(domains: ::std::vec::Vec<(&'static str, Catalogs<C>)>) -> Domains<C> {
+        Domains { domains }
+    }
+
+    /// Gives the catalogs of a domain, if it is available.
+    pub fn domain(&self, domain: &str) -> ::std::option::Option<&Catalogs<C>> {
+        self.domains
+            .iter()
+            .find(|(d, _)| *d == domain)
+            .map(|(_, catalogs)| catalogs)
+    }
+
+    /// Gives the catalog of a domain for a language, if it is available.
+    pub fn get(&self, domain: &str, lang: &str) -> ::std::option::Option<&C> {
+        self.domain(domain).and_then(|catalogs| catalogs.get(lang))
+    }
+
+    /// Gives the catalog of a domain for a language, or the one of the default language of this
+    /// domain if it is not available.
+    pub fn get_or_default(&self, domain: &str, lang: &str) -> ::std::option::Option<&C> {
+        self.domain(domain)
+            .and_then(|catalogs| catalogs.get_or_default(lang))
+    }
+
+    /// The list of the available domains.
+    pub fn domains(&self) -> impl ::std::iter::Iterator<Item = &'static str> + '_ {
+        self.domains.iter().map(|(domain, _)| *domain)
+    }
+
+    /// Gives the catalogs of every domain for a language, falling back to the default language
+    /// of each domain if it is not available.
+    ///
+    /// This is what `i18n!` expects when the `domain` argument is used.
+    pub fn for_language(&self, lang: &str) -> DomainCatalogs<'_, C> {
+        DomainCatalogs {
+            catalogs: self
+                .domains
+                .iter()
+                .filter_map(|(domain, catalogs)| {
+                    catalogs.get_or_default(lang).map(|c| (*domain, c))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The catalogs of several domains, for a single language.
+///
+/// It can be used with the `domain` argument of `i18n!`, like `dgettext` would be.
+pub struct DomainCatalogs<'a, C> {
+    catalogs: ::std::vec::Vec<(&'static str, &'a C)>,
+}
+
+impl<'a, C> DomainCatalogs<'a, C> {
+    /// Gives the catalog of a domain, if it is available.
+    pub fn get(&self, domain: &str) -> ::std::option::Option<&'a C> {
+        self.catalogs
+            .iter()
+            .find(|(d, _)| *d == domain)
+            .map(|(_, catalog)| *catalog)
+    }
+}
+
+impl<C> ::std::ops::Deref for Domains<C> {
+    type Target = [(&'static str, Catalogs<C>)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.domains
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -63,4 +859,230 @@ mod tests {
     fn basic_test() {
         assert_eq!(super::try_format("Hello {}", &[Box::new("world")]).unwrap(), "Hello world");
     }
+
+    #[test]
+    fn fallback_test() {
+        assert_eq!(
+            super::try_format_or_fallback("Salut {1}", "Hello {}", &[Box::new("world")]),
+            "Hello world"
+        );
+        assert_eq!(
+            super::try_format_or_fallback("Salut {}", "Hello {}", &[Box::new("world")]),
+            "Salut world"
+        );
+    }
+
+    #[test]
+    fn write_test() {
+        use std::fmt::Display;
+
+        let mut out = String::from("> ");
+        let name = "Alice";
+        let count = 3;
+        super::write_format(
+            &mut out,
+            "{1}, you have {0} messages",
+            &[&count as &dyn Display, &name],
+        )
+        .unwrap();
+        assert_eq!(out, "> Alice, you have 3 messages");
+
+        assert!(super::write_format(&mut out, "{} }", &[&count as &dyn Display]).is_err());
+        assert!(super::write_format(&mut out, "{} {}", &[&count as &dyn Display]).is_err());
+        assert_eq!(out, "> Alice, you have 3 messages");
+    }
+
+    #[test]
+    fn pattern_test() {
+        let pattern = super::Pattern::parse("{1} and {}").unwrap();
+        assert_eq!(pattern.argc(), 2);
+        let mut out = String::new();
+        pattern.write_format(&mut out, &["a", "b"]).unwrap();
+        assert_eq!(out, "b and b");
+        assert!(pattern.write_format(&mut out, &["a"]).is_err());
+        assert!(super::Pattern::parse("{a}").is_err());
+
+        let hello: &'static str = "Hello {}";
+        let cached = super::cached_pattern(hello).unwrap();
+        assert!(std::ptr::eq(cached, super::cached_pattern(hello).unwrap()));
+        assert_eq!(
+            super::Formatted::cached(hello, &["you"])
+                .unwrap()
+                .to_string(),
+            "Hello you"
+        );
+        assert_eq!(
+            super::Formatted::cached_or_fallback("Salut {2}", hello, &["you"]).to_string(),
+            "Hello you"
+        );
+    }
+
+    #[test]
+    fn catalogs_test() {
+        let catalogs = super::Catalogs::new(vec![("fr", 1), ("en", 2), ("de", 3)]);
+        assert_eq!(catalogs.get("en"), Some(&2));
+        assert_eq!(catalogs.get("eo"), None);
+        assert_eq!(catalogs.get("DE"), Some(&3));
+        assert_eq!(catalogs.get_or_default("eo"), Some(&1));
+        assert_eq!(catalogs.languages().collect::<Vec<_>>(), ["fr", "en", "de"]);
+        assert_eq!(catalogs.default_language(), Some("fr"));
+        assert_eq!(catalogs[2], ("de", 3));
+
+        let empty: super::Catalogs<()> = std::iter::empty().collect();
+        assert_eq!(empty.get_or_default("fr"), None);
+    }
+
+    #[test]
+    fn domains_test() {
+        let domains = super::Domains::new(vec![
+            ("app", super::Catalogs::new(vec![("fr", 1), ("en", 2)])),
+            ("lib", super::Catalogs::new(vec![("en", 3), ("de", 4)])),
+        ]);
+        assert_eq!(domains.get("app", "en"), Some(&2));
+        assert_eq!(domains.get("lib", "en"), Some(&3));
+        assert_eq!(domains.get("lib", "fr"), None);
+        assert_eq!(domains.get_or_default("lib", "fr"), Some(&3));
+        assert_eq!(domains.get("other", "fr"), None);
+        assert_eq!(domains.domains().collect::<Vec<_>>(), ["app", "lib"]);
+        assert_eq!(domains.domain("app").map(|c| c.len()), Some(2));
+
+        let fr = domains.for_language("fr");
+        assert_eq!(fr.get("app"), Some(&1));
+        assert_eq!(fr.get("lib"), Some(&3));
+        assert_eq!(fr.get("other"), None);
+    }
+
+    #[test]
+    fn lazy_catalog_test() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static PARSED: AtomicUsize = AtomicUsize::new(0);
+        let catalog = super::LazyCatalog::new(b"42", |data| {
+            PARSED.fetch_add(1, Ordering::SeqCst);
+            std::str::from_utf8(data).unwrap().parse::<u32>().unwrap()
+        });
+        assert_eq!(PARSED.load(Ordering::SeqCst), 0);
+        assert_eq!(*catalog, 42);
+        assert_eq!(catalog.get(), &42);
+        assert_eq!(PARSED.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn compressed_catalog_test() {
+        let data = miniz_oxide::deflate::compress_to_vec(b"42", 10);
+        let catalog = super::LazyCatalog::compressed(Box::leak(data.into_boxed_slice()), |data| {
+            std::str::from_utf8(data).unwrap().parse::<u32>().unwrap()
+        });
+        assert_eq!(*catalog, 42);
+    }
+
+    #[test]
+    fn po_file_catalog_test() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let path = std::env::temp_dir().join(format!("gettext-utils-{}.po", std::process::id()));
+        let path: &'static str = Box::leak(path.to_str().unwrap().to_string().into_boxed_str());
+        static LOAD_ERRORS: AtomicUsize = AtomicUsize::new(0);
+        static CATALOG: std::sync::OnceLock<super::LazyCatalog<gettext::Catalog>> =
+            std::sync::OnceLock::new();
+        super::set_error_hook(|e| {
+            if let super::TranslationError::Load { .. } = e {
+                LOAD_ERRORS.fetch_add(1, Ordering::SeqCst);
+                // the hook can use the catalog that couldn't be loaded
+                if let Some(catalog) = CATALOG.get() {
+                    catalog.gettext("Error");
+                }
+            }
+        });
+
+        // an empty catalog is used until the file can be loaded
+        let catalog = CATALOG.get_or_init(|| {
+            super::LazyCatalog::from_po_file(path, |data| gettext::Catalog::parse(data).unwrap())
+        });
+        assert_eq!(catalog.gettext("Hello"), "Hello");
+        assert_eq!(LOAD_ERRORS.load(Ordering::SeqCst), 1);
+
+        std::thread::sleep(super::RELOAD_INTERVAL);
+        std::fs::write(path, "msgid \"Hello\"\nmsgstr \"Bonjour\"\n").unwrap();
+        assert_eq!(catalog.gettext("Hello"), "Bonjour");
+
+        std::thread::sleep(super::RELOAD_INTERVAL);
+        std::fs::write(path, "msgid \"Hello\"\nmsgstr \"Salut\"\n").unwrap();
+        assert_eq!(catalog.gettext("Hello"), "Salut");
+
+        std::thread::sleep(super::RELOAD_INTERVAL);
+        std::fs::write(path, "msgid \"Hello\"\nmsgstr 42\n").unwrap();
+        assert_eq!(catalog.gettext("Hello"), "Salut");
+        assert_eq!(LOAD_ERRORS.load(Ordering::SeqCst), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn static_catalog_test() {
+        static MESSAGES: &[super::StaticMessage] = &[
+            (None, "Hello", &["Bonjour"]),
+            (None, "One file", &["Un fichier", "{} fichiers"]),
+            (Some("Greeting"), "Hello", &["Salut"]),
+            (Some("Greeting"), "Hi", &[""]),
+        ];
+        let catalog = super::StaticCatalog::new(MESSAGES, |n| (n > 1) as usize);
+        assert_eq!(catalog.gettext("Hello"), "Bonjour");
+        assert_eq!(catalog.gettext("Bye"), "Bye");
+        assert_eq!(catalog.pgettext("Greeting", "Hello"), "Salut");
+        assert_eq!(catalog.pgettext("Greeting", "Hi"), "Hi");
+        assert_eq!(catalog.ngettext("One file", "{} files", 0), "Un fichier");
+        assert_eq!(catalog.ngettext("One file", "{} files", 2), "{} fichiers");
+        assert_eq!(
+            catalog.npgettext("Greeting", "One file", "{} files", 2),
+            "{} files"
+        );
+    }
+
+    #[test]
+    fn plural_count_test() {
+        assert_eq!(super::plural_count(3usize), 3);
+        assert_eq!(super::plural_count(-1i32), 1);
+        assert_eq!([42u8].iter().map(super::plural_count).next(), Some(42));
+        assert_eq!(super::plural_count(u128::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn lazy_test() {
+        use std::fmt::Display;
+
+        let translations = [("Hello {}", "Salut {}"), ("Bye {}", "Au revoir {1}")];
+        let lookup = |msgid: &str| {
+            translations
+                .iter()
+                .find(|(m, _)| *m == msgid)
+                .map(|(_, t)| *t)
+                .unwrap()
+        };
+        let name = "Bob";
+
+        let hello = super::LazyMessage::new(
+            &lookup,
+            |l| (l("Hello {}"), "Hello {}"),
+            [&name as &dyn Display],
+            false,
+        );
+        assert_eq!(hello.to_string(), "Salut Bob");
+
+        let mut out = String::new();
+        let bye = super::LazyMessage::new(
+            &lookup,
+            |l| (l("Bye {}"), "Bye {}"),
+            [&name as &dyn Display],
+            false,
+        );
+        assert!(std::fmt::write(&mut out, format_args!("{}", bye)).is_err());
+        let bye = super::LazyMessage::new(
+            &lookup,
+            |l| (l("Bye {}"), "Bye {}"),
+            [&name as &dyn Display],
+            true,
+        );
+        assert_eq!(bye.to_string(), "Bye Bob");
+    }
 }
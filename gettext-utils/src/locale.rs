@@ -0,0 +1,417 @@
+//! Language tags, and negotiation of the best language among the available ones.
+
+use crate::Catalogs;
+use std::fmt;
+
+/// A language tag, as used in BCP 47 (`pt-BR`, `zh-Hant-TW`) or by POSIX locales (`pt_BR.UTF-8`, `sr_RS@latin`).
+///
+/// Only the subtags that are useful to choose a translation are kept: extensions, private use subtags,
+/// and the encoding of POSIX locales are ignored. Subtags are normalized to their usual case.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LanguageTag {
+    /// The primary language subtag, in lower case (`pt`).
+    pub language: String,
+    /// The script subtag, in title case (`Hant`).
+    pub script: Option<String>,
+    /// The region subtag, in upper case (`BR`).
+    pub region: Option<String>,
+    /// The variant subtags, or the modifier of a POSIX locale, in lower case.
+    pub variants: Vec<String>,
+}
+
+fn is_alpha(s: &str, min: usize, max: usize) -> bool {
+    (min..=max).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn is_variant(s: &str) -> bool {
+    let alnum = s.chars().all(|c| c.is_ascii_alphanumeric());
+    alnum
+        && ((5..=8).contains(&s.len())
+            || (s.len() == 4 && s.starts_with(|c: char| c.is_ascii_digit())))
+}
+
+impl LanguageTag {
+    /// Parses a language tag. `None` is returned if it is not valid.
+    pub fn parse(tag: &str) -> Option<LanguageTag> {
+        let tag = tag.trim();
+        let (tag, modifier) = match tag.find('@') {
+            Some(i) => (&tag[..i], Some(&tag[i + 1..])),
+            None => (tag, None),
+        };
+        // POSIX locales may have an encoding (ll_CC.UTF-8)
+        let tag = tag.split('.').next().unwrap_or_default();
+
+        let mut subtags = tag.split(['-', '_']).peekable();
+        let language = subtags.next().filter(|l| is_alpha(l, 2, 8))?;
+        let mut res = LanguageTag {
+            language: language.to_ascii_lowercase(),
+            script: None,
+            region: None,
+            variants: vec![],
+        };
+        // extended language subtags
+        while subtags.peek().map(|s| is_alpha(s, 3, 3)).unwrap_or(false) {
+            subtags.next();
+        }
+        if let Some(script) = subtags.next_if(|s| is_alpha(s, 4, 4)) {
+            let (first, rest) = script.split_at(1);
+            res.script = Some(first.to_ascii_uppercase() + &rest.to_ascii_lowercase());
+        }
+        if let Some(region) = subtags.next_if(|s| {
+            is_alpha(s, 2, 2) || (s.len() == 3 && s.chars().all(|c| c.is_ascii_digit()))
+        }) {
+            res.region = Some(region.to_ascii_uppercase());
+        }
+        while let Some(variant) = subtags.next_if(|s| is_variant(s)) {
+            res.variants.push(variant.to_ascii_lowercase());
+        }
+        match subtags.next() {
+            // extensions and private use subtags are ignored
+            Some(singleton) if singleton.len() == 1 => {}
+            Some(_) => return None,
+            None => {}
+        }
+        if let Some(modifier) = modifier {
+            for modifier in modifier.split('-') {
+                if modifier.is_empty() || !modifier.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    return None;
+                }
+                res.variants.push(modifier.to_ascii_lowercase());
+            }
+        }
+        Some(res)
+    }
+
+    /// The name of this tag as a POSIX locale, as used by gettext for the names of `.po` files
+    /// (`pt_BR`, `zh_Hant`, `sr_RS@latin`).
+    pub fn to_posix(&self) -> String {
+        let mut res = self.language.clone();
+        for subtag in self.script.iter().chain(self.region.iter()) {
+            res.push('_');
+            res.push_str(subtag);
+        }
+        if !self.variants.is_empty() {
+            res.push('@');
+            res.push_str(&self.variants.join("-"));
+        }
+        res
+    }
+
+    /// The tags to try, in order, to find a translation for this one: the tag itself, then
+    /// less and less specific versions of it, down to the language alone.
+    ///
+    /// For instance, for `zh-Hant-TW`, this is `zh-Hant-TW`, `zh-Hant`, `zh-TW`, and `zh`.
+    /// Like gettext, the region is removed before the variants: for `sr_RS@latin`, this is
+    /// `sr-RS-latin`, `sr-latin`, `sr-RS`, and `sr`.
+    pub fn fallbacks(&self) -> Vec<LanguageTag> {
+        let mut res = vec![self.clone()];
+        let mut push = |tag: LanguageTag| {
+            if !res.contains(&tag) {
+                res.push(tag);
+            }
+        };
+        let base = LanguageTag {
+            language: self.language.clone(),
+            script: None,
+            region: None,
+            variants: vec![],
+        };
+        push(LanguageTag {
+            region: None,
+            ..self.clone()
+        });
+        push(LanguageTag {
+            variants: vec![],
+            ..self.clone()
+        });
+        push(LanguageTag {
+            script: self.script.clone(),
+            ..base.clone()
+        });
+        push(LanguageTag {
+            region: self.region.clone(),
+            ..base.clone()
+        });
+        push(base);
+        res
+    }
+}
+
+impl std::str::FromStr for LanguageTag {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<LanguageTag, ()> {
+        LanguageTag::parse(s).ok_or(())
+    }
+}
+
+impl fmt::Display for LanguageTag {
+    /// Displays this tag in the BCP 47 format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.language)?;
+        for subtag in self
+            .script
+            .iter()
+            .chain(self.region.iter())
+            .chain(self.variants.iter())
+        {
+            write!(f, "-{}", subtag)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the value of an `Accept-Language` HTTP header.
+///
+/// The languages are returned by order of preference (the ones with the highest quality first).
+/// Invalid entries, the `*` wildcard and languages with a quality of `0` are ignored.
+pub fn parse_accept_language(header: &str) -> Vec<LanguageTag> {
+    let mut langs = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut params = entry.split(';');
+            let tag = LanguageTag::parse(params.next()?)?;
+            let mut quality = 1.0;
+            for param in params {
+                let mut param = param.splitn(2, '=');
+                if param.next()?.trim() == "q" {
+                    quality = param.next()?.trim().parse::<f32>().ok()?;
+                }
+            }
+            if quality > 0.0 && quality <= 1.0 {
+                Some((tag, quality))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    // sort_by is stable: languages with the same quality stay in the same order
+    langs.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    langs.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// The languages the user asked for, by order of preference, according to the environment
+/// variables used by gettext.
+///
+/// The locale is given by the first non-empty variable among `LC_ALL`, `LC_MESSAGES`, and `LANG`.
+/// If it is not the `C` locale, the colon-separated list of languages in `LANGUAGE` comes first.
+/// An empty list is returned if the messages should not be translated.
+pub fn env_languages() -> Vec<String> {
+    languages_from_env(|var| std::env::var(var).ok())
+}
+
+fn languages_from_env<F: Fn(&str) -> Option<String>>(var: F) -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| var(name))
+        .find(|value| !value.is_empty());
+    let locale = match locale {
+        Some(locale) => locale,
+        None => return vec![],
+    };
+    let name = locale.split(['.', '@']).next().unwrap_or_default();
+    if name == "C" || name == "POSIX" {
+        return vec![];
+    }
+
+    let mut langs = var("LANGUAGE")
+        .map(|list| {
+            list.split(':')
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    langs.push(locale);
+    langs
+}
+
+impl<C> Catalogs<C> {
+    pub(crate) fn find_tag(&self, tag: &LanguageTag) -> Option<usize> {
+        self.tags.iter().position(|t| t.as_ref() == Some(tag))
+    }
+
+    /// Finds the best catalog for a list of languages, given by order of preference, without
+    /// using the default language.
+    ///
+    /// For each language, the less specific versions of its tag are tried (see `LanguageTag::fallbacks`),
+    /// and then any catalog for the same language (for instance `pt_BR` for `pt`), before
+    /// moving to the next language.
+    pub fn lookup<T, I>(&self, langs: I) -> Option<(&'static str, &C)>
+    where
+        T: AsRef<str>,
+        I: IntoIterator<Item = T>,
+    {
+        langs
+            .into_iter()
+            .filter_map(|lang| LanguageTag::parse(lang.as_ref()))
+            .find_map(|tag| {
+                tag.fallbacks()
+                    .iter()
+                    .find_map(|t| self.find_tag(t))
+                    .or_else(|| {
+                        self.tags.iter().position(|t| {
+                            t.as_ref()
+                                .map(|t| t.language == tag.language)
+                                .unwrap_or(false)
+                        })
+                    })
+            })
+            .map(|i| (self.catalogs[i].0, &self.catalogs[i].1))
+    }
+
+    /// Same as `lookup`, but falls back to the default language if none of the given languages is available.
+    ///
+    /// `None` is only returned if there are no catalogs at all.
+    pub fn negotiate<T, I>(&self, langs: I) -> Option<(&'static str, &C)>
+    where
+        T: AsRef<str>,
+        I: IntoIterator<Item = T>,
+    {
+        self.lookup(langs).or_else(|| {
+            self.catalogs
+                .first()
+                .map(|(lang, catalog)| (*lang, catalog))
+        })
+    }
+
+    /// Chooses the best catalog for the languages set in the environment (see `env_languages`),
+    /// falling back to the default language.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let catalogs = include_i18n!();
+    /// let (_, catalog) = catalogs.negotiate_env().expect("No catalogs available");
+    /// println!("{}", i18n!(catalog, "Hello, world!"));
+    /// ```
+    pub fn negotiate_env(&self) -> Option<(&'static str, &C)> {
+        self.negotiate(env_languages())
+    }
+
+    /// Chooses the best catalog for the value of an `Accept-Language` HTTP header,
+    /// falling back to the default language.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let catalogs = include_i18n!();
+    /// let (lang, catalog) = catalogs
+    ///     .negotiate_accept_language("pt-BR,pt;q=0.9,en;q=0.5")
+    ///     .expect("No catalogs available");
+    /// ```
+    pub fn negotiate_accept_language(&self, header: &str) -> Option<(&'static str, &C)> {
+        self.negotiate(
+            parse_accept_language(header)
+                .iter()
+                .map(|tag| tag.to_string()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let tag = LanguageTag::parse("zh-hant-tw").unwrap();
+        assert_eq!(tag.to_string(), "zh-Hant-TW");
+        assert_eq!(
+            LanguageTag::parse("pt_BR.UTF-8").unwrap().to_string(),
+            "pt-BR"
+        );
+        assert_eq!(
+            LanguageTag::parse("sr_RS@latin").unwrap().to_string(),
+            "sr-RS-latin"
+        );
+        assert_eq!(LanguageTag::parse("de-CH-1996").unwrap().variants, ["1996"]);
+        assert_eq!(
+            LanguageTag::parse("en-US-x-twain").unwrap().to_string(),
+            "en-US"
+        );
+        assert!(LanguageTag::parse("").is_none());
+        assert!(LanguageTag::parse("e").is_none());
+        assert!(LanguageTag::parse("en-a-b-?").is_some());
+        assert!(LanguageTag::parse("en-US-abc").is_none());
+
+        assert_eq!(LanguageTag::parse("pt-BR").unwrap().to_posix(), "pt_BR");
+        assert_eq!(LanguageTag::parse("zh-hant").unwrap().to_posix(), "zh_Hant");
+        assert_eq!(
+            LanguageTag::parse("sr@latin").unwrap().to_posix(),
+            "sr@latin"
+        );
+        assert_eq!(
+            LanguageTag::parse("de-CH-1996").unwrap().to_posix(),
+            "de_CH@1996"
+        );
+        assert_eq!(
+            LanguageTag::parse("de_CH@1996").unwrap().to_string(),
+            "de-CH-1996"
+        );
+
+        let fallbacks = tag
+            .fallbacks()
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(fallbacks, ["zh-Hant-TW", "zh-Hant", "zh-TW", "zh"]);
+        let tag = LanguageTag::parse("sr_RS@latin").unwrap();
+        let fallbacks = tag
+            .fallbacks()
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(fallbacks, ["sr-RS-latin", "sr-latin", "sr-RS", "sr"]);
+    }
+
+    #[test]
+    fn accept_language_test() {
+        let langs = parse_accept_language("fr;q=0.5, pt-BR,pt;q=0.9,*;q=0.1, en;q=0.5, de;q=0");
+        let langs = langs.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(langs, ["pt-BR", "pt", "fr", "en"]);
+    }
+
+    #[test]
+    fn env_test() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            languages_from_env(move |name| {
+                vars.iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| v.to_string())
+            })
+        };
+        assert_eq!(env(&[("LANG", "fr_FR.UTF-8")]), ["fr_FR.UTF-8"]);
+        assert_eq!(
+            env(&[("LANG", "fr_FR.UTF-8"), ("LC_MESSAGES", "de_DE")]),
+            ["de_DE"]
+        );
+        assert_eq!(env(&[("LC_ALL", ""), ("LC_MESSAGES", "de_DE")]), ["de_DE"]);
+        assert_eq!(
+            env(&[("LANG", "de_DE"), ("LANGUAGE", "sr@latin:pt_BR:")]),
+            ["sr@latin", "pt_BR", "de_DE"]
+        );
+        assert!(env(&[("LANG", "C.UTF-8"), ("LANGUAGE", "fr")]).is_empty());
+        assert!(env(&[("LANGUAGE", "fr")]).is_empty());
+
+        let catalogs = Catalogs::new(vec![("en", 0), ("sr@latin", 1), ("pt", 2)]);
+        assert_eq!(
+            catalogs.negotiate(env(&[("LANG", "sr_RS.UTF-8@latin")])),
+            Some(("sr@latin", &1))
+        );
+        assert_eq!(catalogs.negotiate(env(&[("LANG", "C")])), Some(("en", &0)));
+    }
+
+    #[test]
+    fn negotiate_test() {
+        let catalogs = Catalogs::new(vec![("en", 0), ("pt", 1), ("zh_Hant", 2), ("fr_CA", 3)]);
+        assert_eq!(
+            catalogs.negotiate_accept_language("pt-BR,pt;q=0.9,en;q=0.5"),
+            Some(("pt", &1))
+        );
+        assert_eq!(catalogs.negotiate(["zh-Hant-HK"]), Some(("zh_Hant", &2)));
+        assert_eq!(catalogs.negotiate(["fr", "en"]), Some(("fr_CA", &3)));
+        assert_eq!(catalogs.negotiate(["ja"]), Some(("en", &0)));
+        assert_eq!(catalogs.lookup(["ja"]), None);
+    }
+}
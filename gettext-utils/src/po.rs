@@ -0,0 +1,345 @@
+//! A minimal `.po` parser.
+//!
+//! It is used to compile catalogs to Rust code, and to load `.po` files at runtime
+//! in development mode, by converting them to the `.mo` format.
+
+/// A translated message of a `.po` file.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub context: Option<String>,
+    pub id: String,
+    pub id_plural: Option<String>,
+    pub translations: Vec<String>,
+}
+
+/// The content of a `.po` file.
+#[derive(Debug)]
+pub struct Po {
+    /// The translated messages, sorted by context and message, without the header.
+    pub entries: Vec<Entry>,
+    /// The header, that is the translation of the empty message.
+    pub header: Option<String>,
+    /// The `Plural-Forms` expression of the header, if any.
+    pub plural: Option<String>,
+}
+
+fn unescape(s: &str) -> Result<String, String> {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        res.push(match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('a') => '\x07',
+            Some('b') => '\x08',
+            Some('f') => '\x0c',
+            Some('v') => '\x0b',
+            Some(c @ '"') | Some(c @ '\\') | Some(c @ '\'') | Some(c @ '?') => c,
+            Some(c) => return Err(format!("unknown escape sequence: \\{}", c)),
+            None => return Err("unfinished escape sequence".into()),
+        });
+    }
+    Ok(res)
+}
+
+fn quoted(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        unescape(&s[1..s.len() - 1])
+    } else {
+        Err(format!("expected a quoted string, found `{}`", s))
+    }
+}
+
+#[derive(Default)]
+struct RawEntry {
+    fuzzy: bool,
+    context: Option<String>,
+    id: Option<String>,
+    id_plural: Option<String>,
+    translations: Vec<(usize, String)>,
+}
+
+enum Field {
+    Context,
+    Id,
+    IdPlural,
+    Translation,
+}
+
+impl Po {
+    /// Parses the content of a `.po` file.
+    ///
+    /// Fuzzy, obsolete and untranslated messages are ignored, as `msgfmt` does.
+    pub fn parse(content: &str) -> Result<Po, String> {
+        let mut raw = vec![];
+        let mut current = RawEntry::default();
+        let mut field = None;
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            let error = |e: String| format!("line {}: {}", i + 1, e);
+            let keyword = line.split(char::is_whitespace).next().unwrap_or_default();
+            let new_entry = line.starts_with('#') || keyword == "msgctxt" || keyword == "msgid";
+            if new_entry && matches!(field, Some(Field::Translation)) {
+                raw.push(std::mem::take(&mut current));
+                field = None;
+            }
+            if line.is_empty() || line.starts_with("#~") {
+                continue;
+            }
+            if let Some(flags) = line.strip_prefix("#,") {
+                current.fuzzy = current.fuzzy || flags.split(',').any(|f| f.trim() == "fuzzy");
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('"') {
+                let value = quoted(line).map_err(error)?;
+                match field {
+                    Some(Field::Context) => current
+                        .context
+                        .get_or_insert_with(String::new)
+                        .push_str(&value),
+                    Some(Field::Id) => current.id.get_or_insert_with(String::new).push_str(&value),
+                    Some(Field::IdPlural) => current
+                        .id_plural
+                        .get_or_insert_with(String::new)
+                        .push_str(&value),
+                    Some(Field::Translation) => current
+                        .translations
+                        .last_mut()
+                        .expect("a translation is being parsed")
+                        .1
+                        .push_str(&value),
+                    None => return Err(error("string without keyword".into())),
+                }
+                continue;
+            }
+
+            let value = quoted(&line[keyword.len()..]).map_err(error)?;
+            field = Some(match keyword {
+                "msgctxt" => {
+                    current.context = Some(value);
+                    Field::Context
+                }
+                "msgid" => {
+                    current.id = Some(value);
+                    Field::Id
+                }
+                "msgid_plural" => {
+                    current.id_plural = Some(value);
+                    Field::IdPlural
+                }
+                "msgstr" => {
+                    current.translations.push((0, value));
+                    Field::Translation
+                }
+                _ if keyword.starts_with("msgstr[") && keyword.ends_with(']') => {
+                    let index = keyword["msgstr[".len()..keyword.len() - 1]
+                        .parse()
+                        .map_err(|_| error(format!("invalid plural index in `{}`", keyword)))?;
+                    current.translations.push((index, value));
+                    Field::Translation
+                }
+                _ => return Err(error(format!("unknown keyword `{}`", keyword))),
+            });
+        }
+        if matches!(field, Some(Field::Translation)) {
+            raw.push(current);
+        }
+
+        let mut header = None;
+        let mut entries = vec![];
+        for entry in raw {
+            let id = entry.id.ok_or("message without msgid")?;
+            if id.is_empty() && entry.context.is_none() {
+                header = entry.translations.into_iter().next().map(|(_, h)| h);
+                continue;
+            }
+            let mut translations = entry.translations;
+            translations.sort_by_key(|(i, _)| *i);
+            if entry.fuzzy || translations.iter().all(|(_, t)| t.is_empty()) {
+                continue;
+            }
+            entries.push(Entry {
+                context: entry.context,
+                id,
+                id_plural: entry.id_plural,
+                translations: translations.into_iter().map(|(_, t)| t).collect(),
+            });
+        }
+        entries.sort_by(|a, b| (&a.context, &a.id).cmp(&(&b.context, &b.id)));
+        entries.dedup_by(|a, b| a.context == b.context && a.id == b.id);
+        let plural = header.as_ref().and_then(|header| {
+            header
+                .lines()
+                .find_map(|l| l.trim().strip_prefix("Plural-Forms:"))
+                .and_then(|forms| {
+                    forms
+                        .split(';')
+                        .find_map(|f| f.trim().strip_prefix("plural="))
+                        .map(|p| p.trim().to_string())
+                })
+        });
+        Ok(Po {
+            entries,
+            header,
+            plural,
+        })
+    }
+
+    /// Converts this catalog to the binary `.mo` format, as `msgfmt` would.
+    pub fn to_mo(&self) -> Vec<u8> {
+        let mut messages = self
+            .entries
+            .iter()
+            .map(|e| {
+                let mut original = match e.context {
+                    Some(ref ctx) => format!("{}\u{4}{}", ctx, e.id),
+                    None => e.id.clone(),
+                };
+                if let Some(ref plural) = e.id_plural {
+                    original.push('\0');
+                    original.push_str(plural);
+                }
+                (original, e.translations.join("\0"))
+            })
+            .collect::<Vec<_>>();
+        if let Some(ref header) = self.header {
+            messages.push((String::new(), header.clone()));
+        }
+        messages.sort();
+
+        const HEADER_LEN: usize = 7 * 4;
+        let count = messages.len();
+        let originals_offset = HEADER_LEN;
+        let translations_offset = originals_offset + count * 8;
+        let mut strings_offset = translations_offset + count * 8;
+
+        let mut res = Vec::new();
+        let push = |res: &mut Vec<u8>, n: usize| res.extend_from_slice(&(n as u32).to_le_bytes());
+        for n in [
+            0x9504_12de,
+            0,
+            count,
+            originals_offset,
+            translations_offset,
+            0,
+            strings_offset,
+        ] {
+            push(&mut res, n);
+        }
+        let strings = messages
+            .iter()
+            .map(|(o, _)| o)
+            .chain(messages.iter().map(|(_, t)| t))
+            .collect::<Vec<_>>();
+        for s in &strings {
+            push(&mut res, s.len());
+            push(&mut res, strings_offset);
+            strings_offset += s.len() + 1;
+        }
+        for s in strings {
+            res.extend_from_slice(s.as_bytes());
+            res.push(0);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let po = Po::parse(
+            r#"
+msgid ""
+msgstr ""
+"Language: fr\n"
+"Plural-Forms: nplurals=2; plural=(n > 1);\n"
+
+#: src/main.rs:1
+msgid "Hello"
+msgstr "Bonjour"
+
+msgctxt "Greeting"
+msgid "Hello"
+msgstr ""
+"Salut "
+"\"toi\""
+
+#, fuzzy
+msgid "Fuzzy"
+msgstr "Flou"
+
+msgid "Untranslated"
+msgstr ""
+
+msgid "One"
+msgid_plural "Many"
+msgstr[1] "Plusieurs"
+msgstr[0] "Un"
+
+#, fuzzy
+msgid "Fuzzy again"
+msgstr "Encore flou"
+
+#~ msgid "Old"
+#~ msgstr "Vieux"
+"#,
+        )
+        .unwrap();
+        assert_eq!(po.plural.as_deref(), Some("(n > 1)"));
+        let entries = po
+            .entries
+            .iter()
+            .map(|e| (e.context.as_deref(), e.id.as_str(), e.translations.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (None, "Hello", vec!["Bonjour".to_string()]),
+                (None, "One", vec!["Un".to_string(), "Plusieurs".to_string()]),
+                (Some("Greeting"), "Hello", vec!["Salut \"toi\"".to_string()]),
+            ]
+        );
+        assert!(Po::parse("msgid \"a\"\nmsgwhat \"b\"").is_err());
+    }
+
+    #[test]
+    fn mo_test() {
+        let po = Po::parse(
+            r#"
+msgid ""
+msgstr "Plural-Forms: nplurals=2; plural=(n > 1);\n"
+
+msgid "Hello"
+msgstr "Bonjour"
+
+msgctxt "Greeting"
+msgid "Hello"
+msgstr "Salut"
+
+msgid "One"
+msgid_plural "Many"
+msgstr[0] "Un"
+msgstr[1] "Plusieurs"
+"#,
+        )
+        .unwrap();
+        let catalog = gettext::Catalog::parse(&po.to_mo()[..]).unwrap();
+        assert_eq!(catalog.gettext("Hello"), "Bonjour");
+        assert_eq!(catalog.pgettext("Greeting", "Hello"), "Salut");
+        assert_eq!(catalog.ngettext("One", "Many", 1), "Un");
+        assert_eq!(catalog.ngettext("One", "Many", 2), "Plusieurs");
+        assert_eq!(catalog.gettext("Bye"), "Bye");
+    }
+}
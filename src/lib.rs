@@ -2,148 +2,890 @@
 
 extern crate proc_macro;
 use proc_macro::TokenStream;
-use proc_macro2::{
-    token_stream::IntoIter as TokenIter, Literal, TokenTree,
-};
-use quote::quote;
+use proc_macro2::{Literal, TokenTree};
+use quote::{quote, ToTokens};
 use std::{
+    collections::{btree_map::Entry, BTreeMap},
     env,
-    fs::{create_dir_all, read, File, OpenOptions},
-    io::{BufRead, Read, Seek, SeekFrom, Write},
-    path::Path,
+    fs::{create_dir_all, read_to_string, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::Mutex,
 };
-use syn::Token;
-
-fn is(t: &TokenTree, ch: char) -> bool {
-    match t {
-        TokenTree::Punct(p) => p.as_char() == ch,
-        _ => false,
-    }
-}
-
-fn named_arg(mut input: TokenIter, name: &'static str) -> Option<proc_macro2::TokenStream> {
-    input.next().and_then(|t| match t {
-        TokenTree::Ident(ref i) if i.to_string() == name => {
-            input.next(); // skip "="
-            Some(
-                input
-                    .take_while(|tok| match tok {
-                        TokenTree::Punct(_) => false,
-                        _ => true,
-                    })
-                    .collect(),
-            )
-        }
-        _ => None,
+use syn::{ext::IdentExt, Token};
+
+mod plural;
+mod workspace;
+
+/// An error of a macro, that is reported where it is called.
+fn error<T: std::fmt::Display>(message: T) -> syn::Error {
+    syn::Error::new(proc_macro2::Span::call_site(), message)
+}
+
+/// Gives the output of a macro, or the `compile_error!` corresponding to its error.
+fn output(res: syn::Result<proc_macro2::TokenStream>) -> TokenStream {
+    res.unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+/// Gives a path as a string, to be used in the generated code.
+fn path_str(path: &Path) -> syn::Result<&str> {
+    path.to_str()
+        .ok_or_else(|| error(format!("{} is not a valid UTF-8 path", path.display())))
+}
+
+fn cargo_var(name: &str) -> syn::Result<String> {
+    env::var(name).map_err(|_| {
+        error(format!(
+            "{} is not set. Please use cargo to compile your crate.",
+            name
+        ))
     })
 }
 
-fn root_crate_path() -> std::path::PathBuf {
-    let path = env::var("CARGO_MANIFEST_DIR")
-        .expect("CARGO_MANIFEST_DIR is not set. Please use cargo to compile your crate.");
-    let path = Path::new(&path);
-    if path
-        .parent()
-        .expect("No parent dir")
-        .join("Cargo.toml")
-        .exists()
+/// The environment variable that gives the root of the workspace (relative to the manifest of the crate),
+/// if it is not detected correctly.
+const ROOT_VAR: &str = "GETTEXT_MACROS_ROOT";
+
+/// The root of the workspace of the crate (or of the crate itself if it is not part of a workspace),
+/// where the `po` and `target` directories are.
+fn root_crate_path() -> syn::Result<std::path::PathBuf> {
+    let path = std::path::PathBuf::from(cargo_var("CARGO_MANIFEST_DIR")?);
+    match env::var(ROOT_VAR) {
+        Ok(root) => Ok(path.join(root)),
+        Err(_) => workspace::find_root(&path).map_err(|e| {
+            error(format!(
+                "Couldn't find the workspace of this crate ({}), you can give it with {}",
+                e, ROOT_VAR
+            ))
+        }),
+    }
+}
+
+/// The directory of the translations, as given with the `dir` option (relative to the manifest of the crate),
+/// or the `po` directory at the root of the crate (or of its workspace) by default.
+fn po_dir(dir: Option<String>) -> syn::Result<std::path::PathBuf> {
+    match dir {
+        Some(dir) => Ok(Path::new(&cargo_var("CARGO_MANIFEST_DIR")?).join(dir)),
+        None => Ok(root_crate_path()?.join("po")),
+    }
+}
+
+/// The version of the format of the configuration files, that should be changed every time `Config` changes.
+const CONFIG_VERSION: u32 = 7;
+
+/// What the macros did while compiling a target, to tell if a macro was expanded before or after another one.
+///
+/// It is kept in memory, and not in the configuration files: these files are shared by all the targets of a package
+/// (its library, binaries, tests…), that are compiled by different processes, and sometimes at the same time. A process
+/// can also expand the macros of several targets (rust-analyzer does), so each target has its own session.
+struct Session {
+    /// The name of the target (see `target`).
+    target: String,
+    /// The configuration given in the `[package.metadata.gettext]` section of the manifest, if any.
+    metadata: Option<Config>,
+    /// The configurations read (or written) by the macros, by path, not to parse them again for each macro call.
+    configs: BTreeMap<PathBuf, Config>,
+    /// The domains configured by `init_i18n` (or by `[package.metadata.gettext]`).
+    initialized: Vec<String>,
+    /// Whether `compile_i18n` was expanded.
+    compiled: bool,
+    /// Set when `init_i18n` fails, to not tell that it should be called in the errors of the next macros.
+    init_failed: bool,
+}
+
+impl Session {
+    fn new() -> syn::Result<Session> {
+        let manifest_dir = PathBuf::from(cargo_var("CARGO_MANIFEST_DIR")?);
+        let manifest_path = manifest_dir.join("Cargo.toml");
+        let manifest = match read_to_string(&manifest_path) {
+            Ok(content) => Some(content.parse::<toml::Value>().map_err(|e| {
+                error(format!("Couldn't parse {}: {}", manifest_path.display(), e))
+            })?),
+            Err(_) => None,
+        };
+
+        let package = cargo_var("CARGO_PKG_NAME")?;
+        let library = manifest
+            .as_ref()
+            .and_then(|manifest| library_name(manifest, &manifest_dir, &package));
+        let target = match env::var("CARGO_BIN_NAME") {
+            Ok(bin) => format!("{}@{}", package, bin),
+            Err(_) => {
+                let name = cargo_var("CARGO_CRATE_NAME")?;
+                if library.as_ref() == Some(&name) {
+                    package.clone()
+                } else {
+                    format!("{}@{}", package, name)
+                }
+            }
+        };
+        // the configuration of the manifest belongs to the library, that is compiled before the other targets
+        let owner = if library.is_some() { &package } else { &target };
+        let metadata = match manifest {
+            Some(manifest) => Config::from_metadata(&manifest, &manifest_path, owner)?,
+            None => None,
+        };
+        Ok(Session {
+            target,
+            metadata,
+            configs: BTreeMap::new(),
+            initialized: Vec::new(),
+            compiled: false,
+            init_failed: false,
+        })
+    }
+}
+
+/// What identifies a target in a process: the directory of its manifest, and the names of its binary (if it is one)
+/// and of its crate.
+type TargetKey = (String, Option<String>, String);
+
+/// Gives the session of the target being compiled to `f`.
+///
+/// The sessions are locked while `f` runs, so it should not call this function again.
+fn session<T>(f: impl FnOnce(&mut Session) -> T) -> syn::Result<T> {
+    static SESSIONS: Mutex<BTreeMap<TargetKey, Session>> = Mutex::new(BTreeMap::new());
+    let key = (
+        cargo_var("CARGO_MANIFEST_DIR")?,
+        env::var("CARGO_BIN_NAME").ok(),
+        cargo_var("CARGO_CRATE_NAME")?,
+    );
+    let mut sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    let session = match sessions.entry(key) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(Session::new()?),
+    };
+    Ok(f(session))
+}
+
+/// The name of the library of a package, if it has one.
+fn library_name(manifest: &toml::Value, manifest_dir: &Path, package: &str) -> Option<String> {
+    let lib = manifest.get("lib");
+    if let Some(name) = lib
+        .and_then(|lib| lib.get("name"))
+        .and_then(|name| name.as_str())
     {
-        path.parent().expect("No parent dir").to_path_buf()
+        return Some(name.replace('-', "_"));
+    }
+    if lib.is_some() || manifest_dir.join("src").join("lib.rs").is_file() {
+        Some(package.replace('-', "_"))
     } else {
-        path.to_path_buf()
+        None
     }
 }
 
+/// The target being compiled: the name of its package for its library (and the unit tests of the library, that are
+/// compiled from the same code), or `package@name` for its binaries, tests and examples.
+///
+/// The name of the crate is not enough, because a binary can have the name of the library of its package.
+fn target() -> syn::Result<String> {
+    session(|s| s.target.clone())
+}
+
+/// Where the order in which the macros should be called is explained.
+const ORDER_HELP: &str =
+    "see the \"Order of the macros\" section of the documentation of gettext-macros";
+
+/// The configuration of a crate, as given to `init_i18n`.
+///
+/// It is written by `init_i18n` to `Config::path_of(target)` (or `Config::domain_path(target, domain)` for the other
+/// domains of the target), in TOML, and read by the other macros.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 struct Config {
+    version: u32,
+    /// The target that called `init_i18n` (see `target`). If it is the library of its package, the other targets of the
+    /// package use its configuration when they don't call `init_i18n` themselves.
+    target: String,
+    /// Whether this is another domain of its crate, given to a later call of `init_i18n`.
+    secondary: bool,
     domain: String,
+    /// The directory of the translations, where the `.pot` and `.po` files of each domain are written.
+    po_dir: std::path::PathBuf,
     make_po: bool,
     make_mo: bool,
+    fallback: bool,
+    cache: bool,
+    compiled: bool,
+    reload: bool,
+    compress: bool,
     langs: Vec<String>,
+    /// The options of the languages that have some (the table has to come after the other values in TOML).
+    overrides: BTreeMap<String, LangOptions>,
+}
+
+/// The options of a language, that can be given in a block after it in `init_i18n` (like `gsw { fallback = "de" }`).
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LangOptions {
+    /// The `Plural-Forms` header of its `.po` file, to use instead of the one chosen by `msginit`.
+    plural_forms: Option<String>,
+    /// Another language, whose translations are used for the messages that are not translated in this one.
+    fallback: Option<String>,
+    /// Whether `include_i18n` embeds this language.
+    embed: bool,
+}
+
+impl Default for LangOptions {
+    fn default() -> LangOptions {
+        LangOptions {
+            plural_forms: None,
+            fallback: None,
+            embed: true,
+        }
+    }
 }
 
 impl Config {
-    fn path() -> std::path::PathBuf {
-        Path::new(&env::var("CARGO_TARGET_DIR").unwrap_or_else(|_| {
-            root_crate_path()
-                .join("target")
-                .join("debug")
-                .to_str()
-                .expect("Couldn't compute mo output dir")
-                .into()
+    /// The directory where the configuration of each crate, and the `.mo` files, are written.
+    fn dir() -> syn::Result<std::path::PathBuf> {
+        let target = match env::var("CARGO_TARGET_DIR") {
+            Ok(dir) => std::path::PathBuf::from(dir),
+            Err(_) => root_crate_path()?.join("target").join("debug"),
+        };
+        Ok(target.join("gettext_macros"))
+    }
+
+    /// Where the configuration of the main domain of a target is written.
+    fn path_of(target: &str) -> syn::Result<std::path::PathBuf> {
+        Ok(Config::dir()?.join(target))
+    }
+
+    fn path() -> syn::Result<std::path::PathBuf> {
+        Config::path_of(&target()?)
+    }
+
+    /// Where the configuration of another domain of a target is written
+    /// (package and crate names can't contain dots, so it can't be the one of another target).
+    fn domain_path(target: &str, domain: &str) -> syn::Result<std::path::PathBuf> {
+        Ok(Config::dir()?.join(format!("{}.{}", target, domain)))
+    }
+
+    /// Where this configuration is written.
+    fn own_path(&self) -> syn::Result<std::path::PathBuf> {
+        if self.secondary {
+            Config::domain_path(&self.target, &self.domain)
+        } else {
+            Config::path_of(&self.target)
+        }
+    }
+
+    /// Reads the configuration of the current crate, from its manifest if it has a
+    /// `[package.metadata.gettext]` section, or from the file written by `init_i18n`.
+    ///
+    /// It fails if `init_i18n` was not expanded yet during this compilation.
+    fn read() -> syn::Result<Config> {
+        let (metadata, initialized) = session(|s| {
+            let metadata = s.metadata.clone().filter(|conf| conf.target == s.target);
+            (metadata, s.initialized.clone())
+        })?;
+        // there is no init_i18n call, so the first macro of the target that owns the configuration does its job
+        // (the other ones read the file it writes, without starting a new .pot file)
+        if let Some(conf) = metadata {
+            if !initialized.contains(&conf.domain) {
+                conf.init()?;
+            }
+        }
+        Config::read_current(None)
+    }
+
+    /// Reads the configuration of the main domain of the current target (or of another of its domains), that should
+    /// have been written during this compilation. The targets that don't call `init_i18n` use the configuration of
+    /// the library of their package instead.
+    fn read_current(domain: Option<&str>) -> syn::Result<Config> {
+        let (target, initialized, init_failed) =
+            session(|s| (s.target.clone(), s.initialized.clone(), s.init_failed))?;
+        if init_failed {
+            return Err(error("the translations of this crate are not configured, because of the error in init_i18n!"));
+        }
+        let path = |target: &str| match domain {
+            Some(domain) => Config::domain_path(target, domain),
+            None => Config::path_of(target),
+        };
+        let own = path(&target)?;
+        if own.is_file() {
+            let conf = Config::read_from(&own)?;
+            if !initialized.contains(&conf.domain) {
+                return Err(error(format!(
+                    "init_i18n! should be called before this macro: it wasn't expanded yet, and {} was written by a previous build ({}). If this target doesn't call init_i18n! anymore, remove this file.",
+                    own.display(),
+                    ORDER_HELP
+                )));
+            }
+            return Ok(conf);
+        }
+        // the other targets (like binaries and tests) use the configuration written when their library was compiled
+        let library = path(&cargo_var("CARGO_PKG_NAME")?)?;
+        if library != own && library.is_file() {
+            return Config::read_from(&library);
+        }
+        Err(error(format!(
+            "init_i18n! should be called before this macro: it is needed to configure the translations of this crate ({})",
+            ORDER_HELP
+        )))
+    }
+
+    /// Reads the configuration from the `[package.metadata.gettext]` section of a manifest, if any.
+    fn from_metadata(
+        manifest_value: &toml::Value,
+        manifest: &Path,
+        target: &str,
+    ) -> syn::Result<Option<Config>> {
+        let metadata = match manifest_value
+            .get("package")
+            .and_then(|p| p.get("metadata"))
+            .and_then(|m| m.get("gettext"))
+        {
+            Some(metadata) => metadata.clone(),
+            None => return Ok(None),
+        };
+        let metadata: Metadata = metadata.try_into().map_err(|e| {
+            error(format!(
+                "Invalid [package.metadata.gettext] in {}: {}",
+                manifest.display(),
+                e
+            ))
+        })?;
+        let invalid = |e: String| {
+            error(format!(
+                "Invalid [package.metadata.gettext] in {}: {}",
+                manifest.display(),
+                e
+            ))
+        };
+        let langs = normalize_langs(metadata.langs).map_err(|e| invalid(e.to_string()))?;
+        let mut overrides = BTreeMap::new();
+        for (lang, mut options) in metadata.overrides {
+            let name = normalize_lang(&lang).map_err(|e| invalid(e.to_string()))?;
+            if !langs.contains(&name) {
+                return Err(invalid(format!(
+                    "{} has overrides, but is not in langs",
+                    lang
+                )));
+            }
+            options.check(&name, &langs).map_err(invalid)?;
+            overrides.insert(name, options);
+        }
+        Ok(Some(Config {
+            version: CONFIG_VERSION,
+            target: target.to_string(),
+            secondary: false,
+            domain: match metadata.domain {
+                Some(domain) => domain,
+                None => cargo_var("CARGO_PKG_NAME")?,
+            },
+            po_dir: po_dir(metadata.dir)?,
+            make_po: metadata.po,
+            make_mo: metadata.mo,
+            fallback: metadata.fallback,
+            cache: metadata.cache,
+            compiled: metadata.compiled,
+            reload: metadata.reload,
+            compress: metadata.compress,
+            langs,
+            overrides,
         }))
-        .join("gettext_macros")
-        .join(env::var("CARGO_PKG_NAME").expect("Please build with cargo"))
-    }
-
-    fn read() -> Config {
-        let config = read(Config::path())
-            .expect("Coudln't read domain, make sure to call init_i18n! before");
-        let mut lines = config.lines();
-        let domain = lines
-            .next()
-            .expect("Invalid config file. Make sure to call init_i18n! before this macro")
-            .expect("IO error while reading config");
-        let make_po: bool = lines
-            .next()
-            .expect("Invalid config file. Make sure to call init_i18n! before this macro")
-            .expect("IO error while reading config")
-            .parse()
-            .expect("Couldn't parse make_po");
-        let make_mo: bool = lines
-            .next()
-            .expect("Invalid config file. Make sure to call init_i18n! before this macro")
-            .expect("IO error while reading config")
-            .parse()
-            .expect("Couldn't parse make_mo");
-        Config {
-            domain,
-            make_po,
-            make_mo,
-            langs: lines
-                .map(|l| l.expect("IO error while reading config"))
-                .collect(),
+    }
+
+    /// Finds the configuration of another crate of the workspace (or of another domain of the current crate),
+    /// from its name or its translation domain.
+    fn find(name: &str) -> syn::Result<Config> {
+        if name == cargo_var("CARGO_PKG_NAME")? {
+            return Config::read();
+        }
+        let package = cargo_var("CARGO_PKG_NAME")?;
+        for owner in [target()?, package] {
+            if Config::domain_path(&owner, name)?.is_file() {
+                return Config::read_current(Some(name));
+            }
+        }
+        let own = Config::path()?;
+        let dir = Config::dir()?;
+        let path = dir.join(name);
+        if path.is_file() {
+            return Config::read_from(&path);
+        }
+        let paths = dir
+            .read_dir()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file());
+        for path in paths {
+            // the files of other versions of gettext-macros (or that are not configurations) are ignored
+            let conf = match Config::read_from(&path) {
+                Ok(conf) => conf,
+                Err(_) => continue,
+            };
+            if conf.domain == name {
+                // the configuration of the current crate should not come from a previous build
+                return if path == own {
+                    Config::read()
+                } else {
+                    Ok(conf)
+                };
+            }
+        }
+        Err(error(format!(
+            "Couldn't find the translations of {}. Make sure it is a crate (or a translation domain) that calls init_i18n!, and that it is compiled before this one.",
+            name
+        )))
+    }
+
+    /// The file listing the other domains to which a target added messages, with `domain = "..."`.
+    fn extra_domains_path(target: &str) -> syn::Result<std::path::PathBuf> {
+        Ok(Config::dir()?.join("domains").join(target))
+    }
+
+    fn extra_domains(target: &str) -> syn::Result<Vec<String>> {
+        Ok(std::fs::read_to_string(Config::extra_domains_path(target)?)
+            .map(|domains| domains.lines().map(String::from).collect())
+            .unwrap_or_default())
+    }
+
+    fn add_extra_domain(target: &str, domain: &str) -> syn::Result<()> {
+        if Config::extra_domains(target)?.iter().any(|d| d == domain) {
+            return Ok(());
+        }
+        let path = Config::extra_domains_path(target)?;
+        let io_error =
+            |e: std::io::Error| error(format!("Couldn't write {}: {}", path.display(), e));
+        create_dir_all(Config::dir()?.join("domains")).map_err(io_error)?;
+        let mut out = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(io_error)?;
+        writeln!(out, "{}", domain).map_err(io_error)
+    }
+
+    fn read_from(path: &Path) -> syn::Result<Config> {
+        if let Some(conf) = session(|s| s.configs.get(path).cloned())? {
+            return Ok(conf);
         }
+        let conf = Config::parse(path)?;
+        session(|s| s.configs.insert(path.to_path_buf(), conf.clone()))?;
+        Ok(conf)
     }
 
-    fn write(&self) {
+    fn parse(path: &Path) -> syn::Result<Config> {
+        let config = read_to_string(path).map_err(|_| {
+            error(format!(
+                "Couldn't read {}: call init_i18n! before this macro (it should come first in the source code), or configure the translations in [package.metadata.gettext]",
+                path.display()
+            ))
+        })?;
+        let invalid =
+            |e: toml::de::Error| error(format!("Invalid config file {}: {}", path.display(), e));
+        let config: toml::Value = config.parse().map_err(invalid)?;
+        let version = config.get("version").and_then(|v| v.as_integer());
+        if version != Some(CONFIG_VERSION.into()) {
+            return Err(error(format!(
+                "{} was written by another version of gettext-macros (format {}, expected {}). Please rebuild the crate that calls init_i18n!.",
+                path.display(),
+                version.map(|v| v.to_string()).unwrap_or_else(|| "unknown".into()),
+                CONFIG_VERSION,
+            )));
+        }
+        config.try_into().map_err(invalid)
+    }
+
+    fn write(&self) -> syn::Result<()> {
         // emit file to include
-        create_dir_all(Config::path().parent().unwrap()).expect("Couldn't create output dir");
-        let mut out = File::create(Config::path()).expect("Metadata file couldn't be open");
-        writeln!(out, "{}", self.domain).expect("Couldn't write domain");
-        writeln!(out, "{}", self.make_po).expect("Couldn't write po settings");
-        writeln!(out, "{}", self.make_mo).expect("Couldn't write mo settings");
-        for l in self.langs.clone() {
-            writeln!(out, "{}", l).expect("Couldn't write lang");
+        let path = self.own_path()?;
+        let io_error =
+            |e: std::io::Error| error(format!("Couldn't write {}: {}", path.display(), e));
+        create_dir_all(Config::dir()?).map_err(io_error)?;
+        let config = toml::to_string(self)
+            .map_err(|e| error(format!("Couldn't serialize the configuration: {}", e)))?;
+        std::fs::write(&path, config).map_err(io_error)?;
+        session(|s| s.configs.insert(path, self.clone()))?;
+        Ok(())
+    }
+
+    /// The options of a language of this crate.
+    fn options(&self, lang: &str) -> LangOptions {
+        self.overrides.get(lang).cloned().unwrap_or_default()
+    }
+
+    /// The directory of the `.pot` and `.po` files of this domain.
+    fn domain_dir(&self) -> std::path::PathBuf {
+        self.po_dir.join(&self.domain)
+    }
+
+    /// Writes this configuration for the other macros and crates, and starts a new `.pot` file.
+    fn init(&self) -> syn::Result<()> {
+        if self.compress && cfg!(not(feature = "compress")) {
+            return Err(error(COMPRESS_FEATURE_NEEDED));
+        }
+        self.write()?;
+        session(|s| s.initialized.push(self.domain.clone()))?;
+        if self.secondary {
+            // makes sure that compile_i18n will update this domain, with the other ones of the crate
+            Config::add_extra_domain(&self.target, &self.domain)?;
+        } else {
+            // the messages of other domains will be listed again by i18n! and t!
+            let _ = std::fs::remove_file(Config::extra_domains_path(&self.target)?);
+        }
+
+        // write base .pot
+        let dir = self.domain_dir();
+        let path = dir.join(format!("{}.pot", self.domain));
+        let io_error =
+            |e: std::io::Error| error(format!("Couldn't write {}: {}", path.display(), e));
+        create_dir_all(&dir).map_err(io_error)?;
+        let mut pot = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .map_err(io_error)?;
+        pot.write_all(
+            &format!(
+                r#"msgid ""
+msgstr ""
+"Project-Id-Version: {}\n"
+"Report-Msgid-Bugs-To: \n"
+"POT-Creation-Date: 2018-06-15 16:33-0700\n"
+"PO-Revision-Date: YEAR-MO-DA HO:MI+ZONE\n"
+"Last-Translator: FULL NAME <EMAIL@ADDRESS>\n"
+"Language-Team: LANGUAGE <LL@li.org>\n"
+"Language: \n"
+"MIME-Version: 1.0\n"
+"Content-Type: text/plain; charset=UTF-8\n"
+"Content-Transfer-Encoding: 8bit\n"
+"Plural-Forms: nplurals=INTEGER; plural=EXPRESSION;\n"
+"#,
+                self.domain
+            )
+            .into_bytes(),
+        )
+        .map_err(io_error)
+    }
+}
+
+/// The options that can be given to `init_i18n` (or in `[package.metadata.gettext]`).
+const OPTIONS: &[&str] = &[
+    "dir", "po", "mo", "location", "fallback", "cache", "compiled", "reload", "compress",
+];
+
+/// The options that can be given to each language, in a block after it.
+const LANG_OPTIONS: &[&str] = &["plural_forms", "fallback", "embed"];
+
+struct InitI18nCall {
+    domain: syn::LitStr,
+    dir: Option<syn::LitStr>,
+    options: Vec<(syn::Ident, syn::LitBool)>,
+    langs: Vec<String>,
+    overrides: BTreeMap<String, LangOptions>,
+}
+
+impl syn::parse::Parse for InitI18nCall {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let domain = input.parse::<syn::LitStr>().map_err(|e| {
+            syn::Error::new(
+                e.span(),
+                "expected a translation domain (for instance \"myapp\")",
+            )
+        })?;
+        let mut dir = None;
+        let mut options: Vec<(syn::Ident, syn::LitBool)> = vec![];
+        let mut langs = vec![];
+        let mut overrides = vec![];
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+
+            if input.peek(syn::Ident::peek_any) && input.peek2(Token![=]) {
+                let name = input.call(syn::Ident::parse_any)?;
+                input.parse::<Token![=]>()?;
+                if !OPTIONS.iter().any(|o| name == o) {
+                    return Err(syn::Error::new(
+                        name.span(),
+                        format!(
+                            "unknown option `{}`, expected one of: {}",
+                            name,
+                            OPTIONS.join(", ")
+                        ),
+                    ));
+                }
+                if options.iter().any(|(n, _)| *n == name) || (name == "dir" && dir.is_some()) {
+                    return Err(syn::Error::new(
+                        name.span(),
+                        format!("`{}` is given twice", name),
+                    ));
+                }
+                if name == "dir" {
+                    dir = Some(input.parse::<syn::LitStr>().map_err(|e| {
+                        syn::Error::new(
+                            e.span(),
+                            "`dir` should be a path, as a string (like \"locales\")",
+                        )
+                    })?);
+                    continue;
+                }
+                let value = input.parse::<syn::LitBool>().map_err(|e| {
+                    syn::Error::new(e.span(), format!("`{}` should be `true` or `false`", name))
+                })?;
+                options.push((name, value));
+                continue;
+            }
+
+            let (lang, span) = if input.peek(syn::LitStr) {
+                let lit = input.parse::<syn::LitStr>()?;
+                (lit.value(), lit.span())
+            } else if input.peek(syn::Ident::peek_any) {
+                // some languages are keywords, like `as` (Assamese)
+                let ident = input.call(syn::Ident::parse_any)?.unraw();
+                (ident.to_string(), ident.span())
+            } else {
+                return Err(input.error("expected an option (like `fallback = true`), or a language (like `fr` or \"pt-BR\")"));
+            };
+            let name = match gettext_utils::LanguageTag::parse(&lang) {
+                Some(tag) => tag.to_posix(),
+                None => {
+                    return Err(syn::Error::new(
+                        span,
+                        format!("`{}` is not a valid language: it should be a BCP 47 language tag (like pt-BR) or a POSIX locale (like pt_BR)", lang),
+                    ))
+                }
+            };
+            if langs.contains(&name) {
+                return Err(syn::Error::new(
+                    span,
+                    format!("the language `{}` is listed twice", lang),
+                ));
+            }
+            langs.push(name.clone());
+
+            if input.peek(syn::token::Brace) {
+                let block;
+                let brace = syn::braced!(block in input);
+                overrides.push((name, LangOptions::parse(&block)?, brace.span));
+            }
+        }
+
+        let overrides = overrides
+            .into_iter()
+            .map(|(lang, mut options, span)| {
+                options
+                    .check(&lang, &langs)
+                    .map_err(|e| syn::Error::new(span, e))?;
+                Ok((lang, options))
+            })
+            .collect::<syn::Result<_>>()?;
+        Ok(InitI18nCall {
+            domain,
+            dir,
+            options,
+            langs,
+            overrides,
+        })
+    }
+}
+
+impl LangOptions {
+    /// Parses the content of the block of options of a language in `init_i18n`.
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut options = LangOptions::default();
+        let mut given: Vec<syn::Ident> = vec![];
+        while !input.is_empty() {
+            let name = input.call(syn::Ident::parse_any)?;
+            input.parse::<Token![=]>()?;
+            if !LANG_OPTIONS.iter().any(|o| name == o) {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!(
+                        "unknown language option `{}`, expected one of: {}",
+                        name,
+                        LANG_OPTIONS.join(", ")
+                    ),
+                ));
+            }
+            if given.contains(&name) {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!("`{}` is given twice", name),
+                ));
+            }
+            if name == "plural_forms" {
+                let forms = input.parse::<syn::LitStr>().map_err(|e| {
+                    syn::Error::new(
+                        e.span(),
+                        "`plural_forms` should be a string (like \"nplurals=2; plural=(n != 1);\")",
+                    )
+                })?;
+                options.plural_forms = Some(forms.value());
+            } else if name == "fallback" {
+                options.fallback = Some(if input.peek(syn::Ident::peek_any) {
+                    input.call(syn::Ident::parse_any)?.unraw().to_string()
+                } else {
+                    input
+                        .parse::<syn::LitStr>()
+                        .map_err(|e| {
+                            syn::Error::new(
+                                e.span(),
+                                "`fallback` should be a language (like `de` or \"pt-BR\")",
+                            )
+                        })?
+                        .value()
+                });
+            } else {
+                options.embed = input
+                    .parse::<syn::LitBool>()
+                    .map_err(|e| {
+                        syn::Error::new(e.span(), format!("`{}` should be `true` or `false`", name))
+                    })?
+                    .value;
+            }
+            given.push(name);
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(options)
+    }
+
+    /// Checks the options of `lang`, one of `langs`, and gives the name of its fallback language as a POSIX locale.
+    fn check(&mut self, lang: &str, langs: &[String]) -> Result<(), String> {
+        if let Some(ref forms) = self.plural_forms {
+            let expr = forms
+                .split(';')
+                .find_map(|f| f.trim().strip_prefix("plural="))
+                .filter(|_| forms.split(';').any(|f| f.trim().starts_with("nplurals=")))
+                .ok_or_else(|| {
+                    format!(
+                        "the plural forms of {} should look like \"nplurals=2; plural=(n != 1);\"",
+                        lang
+                    )
+                })?;
+            plural::compile_plural(expr)
+                .map_err(|e| format!("invalid plural forms for {}: {}", lang, e))?;
+        }
+        if let Some(ref fallback) = self.fallback {
+            let name = normalize_lang(fallback).map_err(|e| e.to_string())?;
+            if name == lang || !langs.contains(&name) {
+                return Err(format!(
+                    "the fallback of {} should be another language of the list, and not {}",
+                    lang, fallback
+                ));
+            }
+            self.fallback = Some(name);
+        }
+        Ok(())
+    }
+}
+
+/// Validates a list of languages, and gives their names as POSIX locales, that are used for the names of
+/// the `.po` and `.mo` files, and as the keys of `include_i18n`.
+fn normalize_langs(langs: Vec<String>) -> syn::Result<Vec<String>> {
+    let mut res: Vec<String> = vec![];
+    for lang in langs {
+        let name = normalize_lang(&lang)?;
+        if res.contains(&name) {
+            return Err(error(format!("the language {} is listed twice", lang)));
+        }
+        res.push(name);
+    }
+    Ok(res)
+}
+
+fn normalize_lang(lang: &str) -> syn::Result<String> {
+    gettext_utils::LanguageTag::parse(lang)
+        .map(|tag| tag.to_posix())
+        .ok_or_else(|| {
+            error(format!(
+                "{} is not a valid language: it should be a BCP 47 language tag (like pt-BR) or a POSIX locale (like pt_BR)",
+                lang
+            ))
+        })
+}
+
+/// The `[package.metadata.gettext]` section of a manifest, that can be used instead of `init_i18n`.
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Metadata {
+    domain: Option<String>,
+    dir: Option<String>,
+    langs: Vec<String>,
+    po: bool,
+    mo: bool,
+    /// Accepted for consistency with `init_i18n`, but locations are not written yet.
+    #[allow(dead_code)]
+    location: bool,
+    fallback: bool,
+    cache: bool,
+    compiled: bool,
+    reload: bool,
+    compress: bool,
+    overrides: BTreeMap<String, LangOptions>,
+}
+
+impl Default for Metadata {
+    fn default() -> Metadata {
+        Metadata {
+            domain: None,
+            dir: None,
+            langs: vec![],
+            po: true,
+            mo: true,
+            location: true,
+            fallback: false,
+            cache: false,
+            compiled: false,
+            reload: false,
+            compress: false,
+            overrides: BTreeMap::new(),
         }
     }
 }
 
 trait Message {
     fn writable(&self) -> bool;
+    fn domain(&self) -> Option<String>;
     fn content(&self) -> String;
     fn context(&self) -> Option<String>;
     fn plural(&self) -> Option<String>;
 
-    fn write(&self) {
+    fn write(&self) -> syn::Result<()> {
         if !self.writable() {
-            return;
+            return Ok(());
         }
 
-        let config = Config::read();
+        let mut config = Config::read()?;
+        // the library may be compiled again at any time (even while this target is compiled), starting a new .pot file:
+        // the messages of its other targets would be lost, so only the ones of the library are translated
+        if config.target != target()? {
+            return Ok(());
+        }
+        if session(|s| s.compiled)? {
+            return Err(error(format!(
+                "this message comes after compile_i18n!, so it can't be translated: compile_i18n! should be called after all the messages ({})",
+                ORDER_HELP
+            )));
+        }
+        if let Some(domain) = self.domain().filter(|d| *d != config.domain) {
+            // makes sure that compile_i18n will update this domain too
+            Config::add_extra_domain(&config.target, &domain)?;
+            config = Config::find(&domain)?;
+        }
 
+        let path = config.domain_dir().join(format!("{}.pot", config.domain));
+        let io_error =
+            |e: std::io::Error| error(format!("Couldn't write {}: {}", path.display(), e));
         let mut pot = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(format!("po/{0}/{0}.pot", config.domain))
-            .expect("Couldn't open .pot file");
+            .truncate(false)
+            .open(&path)
+            .map_err(io_error)?;
 
         let mut contents = String::new();
-        pot.read_to_string(&mut contents)
-            .expect("IO error while reading .pot file");
-        pot.seek(SeekFrom::End(0))
-            .expect("IO error while seeking .pot file to end");
+        pot.read_to_string(&mut contents).map_err(io_error)?;
+        pot.seek(SeekFrom::End(0)).map_err(io_error)?;
 
         let already_exists = self.content().is_empty()
             || contents.contains(&format!(
@@ -158,7 +900,7 @@ r#"msgctxt "{}"
                 self.content()
             ));
         if already_exists {
-            return;
+            return Ok(());
         }
 
         let prefix = if let Some(c) = self.context() {
@@ -181,7 +923,7 @@ msgstr[0] ""
                 )
                 .into_bytes(),
             )
-            .expect("Couldn't write message to .pot (plural)");
+            .map_err(io_error)
         } else {
             pot.write_all(
                 &format!(
@@ -193,27 +935,32 @@ msgstr ""
                 )
                 .into_bytes(),
             )
-            .expect("Couldn't write message to .pot");
+            .map_err(io_error)
         }
     }
 }
 
 struct I18nCall {
     catalog: syn::Expr,
+    domain: Option<syn::LitStr>,
     context: Option<syn::LitStr>,
     msg: syn::Expr,
     plural: Option<syn::Expr>,
+    count: Option<syn::Expr>,
     format_args: Option<syn::punctuated::Punctuated<syn::Expr, syn::Token![,]>>,
 }
 
 mod kw {
+    syn::custom_keyword!(domain);
     syn::custom_keyword!(context);
+    syn::custom_keyword!(count);
 }
 
 impl syn::parse::Parse for I18nCall {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let catalog = input.parse()?;
         input.parse::<Token![,]>()?;
+        let domain = parse_domain(input)?;
         let context = if input.parse::<kw::context>().is_ok() {
             input.parse::<Token![=]>()?;
             let ctx = input.parse().ok();
@@ -222,28 +969,256 @@ impl syn::parse::Parse for I18nCall {
         } else {
             None
         };
-        let msg = input.parse()?;
-        let plural = if input.parse::<Token![,]>().is_ok() {
+        let msg: syn::Expr = input.parse()?;
+        // `count` is only the keyword when it is followed by `=`: it can be a plural form too
+        let count_next = |input: syn::parse::ParseStream| {
+            input.peek(Token![,]) && input.peek2(kw::count) && input.peek3(Token![=])
+        };
+        let plural = if input.peek(Token![,]) && !count_next(input) {
+            input.parse::<Token![,]>()?;
             input.parse().ok()
         } else {
             None
         };
-        let format_args = if input.parse::<Token![;]>().is_ok() {
-            syn::punctuated::Punctuated::parse_terminated(input).ok()
+        let count = if count_next(input) {
+            input.parse::<Token![,]>()?;
+            let kw = input.parse::<kw::count>()?;
+            input.parse::<Token![=]>()?;
+            if plural.is_none() {
+                return Err(syn::Error::new(
+                    kw.span,
+                    "`count` can only be used for messages with a plural form",
+                ));
+            }
+            Some(input.parse()?)
         } else {
             None
         };
+        let format_args: Option<syn::punctuated::Punctuated<syn::Expr, Token![,]>> =
+            if input.parse::<Token![;]>().is_ok() {
+                syn::punctuated::Punctuated::parse_terminated(input).ok()
+            } else {
+                None
+            };
+
+        if plural.is_some()
+            && count.is_none()
+            && format_args.as_ref().map(|a| a.is_empty()).unwrap_or(true)
+        {
+            return Err(syn::Error::new_spanned(
+                &msg,
+                "this message has a plural form, but no count: add `count = ...` after the plural form, or a format argument",
+            ));
+        }
 
         Ok(I18nCall {
             catalog,
+            domain,
             context,
             msg,
             plural,
+            count,
             format_args,
         })
     }
 }
 
+/// Parses the optional `domain = "..."` argument of a message.
+fn parse_domain(input: syn::parse::ParseStream) -> syn::Result<Option<syn::LitStr>> {
+    if input.parse::<kw::domain>().is_ok() {
+        input.parse::<Token![=]>()?;
+        let domain = input.parse()?;
+        input.parse::<Token![,]>()?;
+        Ok(Some(domain))
+    } else {
+        Ok(None)
+    }
+}
+
+/// An identifier of the generated code, that can't clash with the ones of the arguments of the macros.
+fn hygienic(name: &str) -> proc_macro2::Ident {
+    proc_macro2::Ident::new(name, proc_macro2::Span::mixed_site())
+}
+
+impl I18nCall {
+    /// Generates the code that evaluates the message, its plural form, its count and its format arguments once,
+    /// before `body`, that can use them with the identifiers `msg`, `plural`, `count` and `arg0`, `arg1`… (see `hygienic`).
+    ///
+    /// If `catalog` is given, a reference to the catalog is bound to it too.
+    ///
+    /// Like with `format_args!`, they are bound by a `match`, so that the temporary values they borrow live until the
+    /// end of the statement.
+    fn bind(
+        &self,
+        catalog: Option<&proc_macro2::Ident>,
+        body: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let mut names = vec![];
+        let mut values = vec![];
+        if let Some(catalog_ref) = catalog {
+            let catalog = &self.catalog;
+            names.push(catalog_ref.clone());
+            values.push(quote!(&(#catalog)));
+        }
+        names.push(hygienic("msg"));
+        values.push(self.msg.to_token_stream());
+        if let Some(ref pl) = self.plural {
+            names.push(hygienic("plural"));
+            values.push(pl.to_token_stream());
+        }
+        for (i, arg) in self.format_args.iter().flatten().enumerate() {
+            names.push(self.arg(i));
+            values.push(quote!(&(#arg)));
+        }
+
+        let count = if self.plural.is_none() {
+            None
+        } else if let Some(ref count) = self.count {
+            Some(quote!(::gettext_utils::plural_count(#count)))
+        } else {
+            let first = self.arg(0);
+            Some(quote!(*#first as u64))
+        };
+        let count = count.map(|count| {
+            let name = hygienic("count");
+            quote!(let #name: u64 = #count;)
+        });
+        quote!(match (#(#values,)*) {
+            (#(#names,)*) => {
+                #count
+                #body
+            }
+        })
+    }
+
+    /// The identifier of a format argument, in the code generated by `bind`.
+    fn arg(&self, i: usize) -> proc_macro2::Ident {
+        hygienic(&format!("arg{}", i))
+    }
+
+    /// Generates the code to get the translated message, and the one to get the original message
+    /// (the one that would be used without translations), in the body of `bind`.
+    ///
+    /// If a domain was given, `catalog` should be a `gettext_utils::DomainCatalogs`, and the original
+    /// message is used if it doesn't contain this domain.
+    fn translation(
+        &self,
+        catalog: &dyn quote::ToTokens,
+    ) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        if let Some(ref domain) = self.domain {
+            let domain_catalog = hygienic("domain_catalog");
+            let (gettext_call, original) = self.catalog_translation(&domain_catalog);
+            return (
+                quote!(match (#catalog).get(#domain) {
+                    ::std::option::Option::Some(#domain_catalog) => #gettext_call,
+                    ::std::option::Option::None => #original,
+                }),
+                original,
+            );
+        }
+        self.catalog_translation(catalog)
+    }
+
+    /// Same as `translation`, ignoring the domain.
+    fn catalog_translation(
+        &self,
+        catalog: &dyn quote::ToTokens,
+    ) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let content = hygienic("msg");
+        if self.plural.is_some() {
+            let pl = hygienic("plural");
+            let count = hygienic("count");
+            let original = quote!(if #count == 1 { #content } else { #pl });
+            if let Some(ref c) = self.context {
+                (
+                    quote!(#catalog.npgettext(#c, #content, #pl, #count)),
+                    original,
+                )
+            } else {
+                (quote!(#catalog.ngettext(#content, #pl, #count)), original)
+            }
+        } else if let Some(ref c) = self.context {
+            (quote!(#catalog.pgettext(#c, #content)), quote!(#content))
+        } else {
+            (quote!(#catalog.gettext(#content)), quote!(#content))
+        }
+    }
+
+    /// Generates an array of `&dyn Display` references to the format arguments, in the body of `bind`.
+    fn format_args_array(&self) -> proc_macro2::TokenStream {
+        let fargs = (0..self.format_args.iter().flatten().count()).map(|i| self.arg(i));
+        quote!([#(#fargs as &dyn ::std::fmt::Display),*])
+    }
+
+    /// Same as `format_args_array`, but as a slice.
+    fn format_args_refs(&self) -> proc_macro2::TokenStream {
+        let fargs = self.format_args_array();
+        quote!(&#fargs as &[&dyn ::std::fmt::Display])
+    }
+
+    /// The options of `init_i18n` used to translate this message.
+    ///
+    /// The configuration is only needed for the messages that are written to the `.pot` file: the other ones
+    /// (like `i18n!(cat, msg)`) can be translated in crates that don't call `init_i18n`, with the default options.
+    ///
+    /// The translations are only cached when the message and its plural form are string literals: otherwise they
+    /// may be borrowed from the message, that is not `'static`.
+    fn options(&self) -> syn::Result<MessageOptions> {
+        let conf = if self.writable() {
+            Some(Config::read()?)
+        } else {
+            Config::read().ok()
+        };
+        Ok(conf
+            .map(|conf| MessageOptions {
+                fallback: conf.fallback,
+                cache: conf.cache
+                    && self.writable()
+                    && self.plural.iter().all(|p| extract_str_lit(p).is_some()),
+            })
+            .unwrap_or_default())
+    }
+
+    /// Generates a `gettext_utils::Formatted` for this message, with the given options, in the body of `bind`.
+    ///
+    /// `gettext_utils::Formatted` should be in scope.
+    fn formatted(&self, options: &MessageOptions) -> proc_macro2::TokenStream {
+        let (gettext_call, original) = self.translation(&self.catalog);
+        let fargs = self.format_args_refs();
+        match (options.cache, options.fallback) {
+            (false, false) => quote!(
+                Formatted::new(#gettext_call, #fargs).expect("Error while formatting message")
+            ),
+            (false, true) => quote!(Formatted::new_or_fallback(#gettext_call, #original, #fargs)),
+            (true, false) => quote!(
+                Formatted::cached(#gettext_call, #fargs).expect("Error while formatting message")
+            ),
+            (true, true) => quote!(Formatted::cached_or_fallback(#gettext_call, #original, #fargs)),
+        }
+    }
+}
+
+/// The options of `init_i18n` that change the code generated for a message.
+#[derive(Default)]
+struct MessageOptions {
+    fallback: bool,
+    cache: bool,
+}
+
+struct I18nWriteCall {
+    writer: syn::Expr,
+    message: I18nCall,
+}
+
+impl syn::parse::Parse for I18nWriteCall {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let writer = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let message = input.parse()?;
+        Ok(I18nWriteCall { writer, message })
+    }
+}
+
 fn extract_str_lit(expr: &syn::Expr) -> Option<String> {
     match *expr {
         syn::Expr::Lit(syn::ExprLit { lit : syn::Lit::Str(ref s), attrs: _ }) => Some(s.value()),
@@ -256,6 +1231,10 @@ impl Message for I18nCall {
         extract_str_lit(&self.msg).is_some()
     }
 
+    fn domain(&self) -> Option<String> {
+        self.domain.as_ref().map(|d| d.value())
+    }
+
     fn content(&self) -> String {
         extract_str_lit(&self.msg).unwrap_or_default().replace("\"", "\\\"").replace('\n', "\\n")
     }
@@ -270,6 +1249,7 @@ impl Message for I18nCall {
 }
 
 struct TCall {
+    domain: Option<syn::LitStr>,
     context: Option<syn::LitStr>,
     msg: syn::LitStr,
     plural: Option<syn::LitStr>,
@@ -277,6 +1257,7 @@ struct TCall {
 
 impl syn::parse::Parse for TCall {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let domain = parse_domain(input)?;
         let context = if input.parse::<kw::context>().is_ok() {
             input.parse::<Token![=]>()?;
             let ctx = input.parse().ok();
@@ -294,6 +1275,7 @@ impl syn::parse::Parse for TCall {
         };
 
         Ok(TCall {
+            domain,
             context,
             msg,
             plural,
@@ -306,6 +1288,10 @@ impl Message for TCall {
         true
     }
 
+    fn domain(&self) -> Option<String> {
+        self.domain.as_ref().map(|d| d.value())
+    }
+
     fn content(&self) -> String {
         self.msg.value().replace("\"", "\\\"").replace('\n', "\\n")
     }
@@ -355,21 +1341,26 @@ impl Message for TCall {
 /// t!($singular, $plural)
 /// t!(context = $ctx, $singular)
 /// t!(context = $ctx, $singular, $plural)
+/// t!(domain = $domain, ...)
 /// ```
 ///
-/// Where `$singular`, `$plural` and `$ctx` all are `str` literals (and not variables, expressions or literal of any other type).
+/// Where `$singular`, `$plural`, `$ctx` and `$domain` all are `str` literals (and not variables, expressions or literal of any other type).
+///
+/// If a domain is given, the message is added to the `.pot` file of this domain, instead of the one of the current crate
+/// (see `i18n` for more details).
 #[proc_macro]
 pub fn t(input: TokenStream) -> TokenStream {
     let message = syn::parse_macro_input!(input as TCall);
-    message.write();
-    let msg = message.content();
-    if let Some(pl) = message.plural.clone() {
-        quote!(
-            (#msg, #pl)
-        ).into()
-    } else {
-        quote!(#msg).into()
-    }
+    output(message.write().map(|_| {
+        let msg = message.content();
+        if let Some(pl) = message.plural.clone() {
+            quote!(
+                (#msg, #pl)
+            )
+        } else {
+            quote!(#msg)
+        }
+    }))
 }
 
 /// Marks a string as translatable and translate it at runtime.
@@ -387,6 +1378,16 @@ pub fn t(input: TokenStream) -> TokenStream {
 /// is translated in Esperanto as `Saluton {name}!`, and that you call this function without
 /// any format argument (as expected in the original English string), it will panic.
 ///
+/// If `fallback = true` was given to `init_i18n`, the original message is formatted instead,
+/// and the error is reported to the hook registered with `gettext_utils::set_error_hook`.
+///
+/// # Caching
+///
+/// If `cache = true` was given to `init_i18n`, each translation is only parsed the first time it is
+/// formatted. This requires the catalog to be borrowed for `'static` (a `static` catalog, or one that was leaked),
+/// so that the translated strings can be used as cache keys. Messages that are not string literals (like `i18n!(cat, &s)`)
+/// are never cached, since their translations may be borrowed from them.
+///
 /// # Examples
 ///
 /// Basic usage:
@@ -421,12 +1422,29 @@ pub fn t(input: TokenStream) -> TokenStream {
 /// i18n!(cat, "What a nice flower!", "What a nice garden!"; flowers_count);
 /// ```
 ///
+/// With a count that is not the first format argument, or that is not displayed at all:
+///
+/// ```rust,ignore
+/// let unread = 3;
+/// i18n!(cat, "{0}, you have a new message", "{0}, you have {1} new messages", count = unread; name, unread);
+/// i18n!(cat, "Delete this file?", "Delete these files?", count = files.len());
+/// ```
+///
+/// With a message of another domain, like `dgettext` would do:
+///
+/// ```rust,ignore
+/// // a gettext_utils::DomainCatalogs
+/// let cats = include_i18n!("plume", "plume-models").for_language("fr");
+/// i18n!(cats, domain = "plume-models", "This post was deleted");
+/// ```
+///
 /// With all available options:
 ///
 /// ```rust,ignore
 /// let updates = 69;
 /// i18n!(
-///     cat,
+///     cats,
+///     domain = "my_app",
 ///     context = "The notification when updates are available.",
 ///     "There is {} app update available."
 ///     "There are {} app updates available.";
@@ -439,12 +1457,19 @@ pub fn t(input: TokenStream) -> TokenStream {
 /// This macro expects:
 ///
 /// - first, the expression to get the translation catalog to use
+/// - then, optionally, the `domain` named argument, a `str` literal. The message is then added to the `.pot` file of this
+///   domain, that should have been initialized by another crate calling `init_i18n`, and `compile_i18n` will update its
+///   translations too. The catalog should then be a `gettext_utils::DomainCatalogs`, as given by `gettext_utils::Domains::for_language`,
+///   and the original message is used if this domain is not available.
 /// - then, optionally, the `context` named argument, that is a string that will be shown
 ///   to translators. It should be a `str` literal, because it needs to be known at compile time.
 /// - the message to translate. It can either be a string literal, or an expression, but if you use the later
 ///   make sure that the string is correctly added to the `.pot` file with `t`.
 /// - if this message has a plural version, it should come after. Here too, both string literals or other expressions
 ///   are allowed
+/// - for messages with a plural version, optionally, the `count` named argument: the number used to choose
+///   between the singular and the plural forms. It can be any expression of an integer type. If it is not given,
+///   the first format argument is used as the count.
 ///
 /// All these arguments should be separated by commas.
 ///
@@ -453,44 +1478,140 @@ pub fn t(input: TokenStream) -> TokenStream {
 #[proc_macro]
 pub fn i18n(input: TokenStream) -> TokenStream {
     let message = syn::parse_macro_input!(input as I18nCall);
-    message.write();
-
-    let gettext_call = message.catalog.clone();
-    let content = message.msg;
-    let gettext_call = if let Some(pl) = message.plural {
-        let count = message
-            .format_args
-            .clone()
-            .and_then(|args| args.first().cloned());
-        if let Some(c) = message.context {
-            quote!(
-                #gettext_call.npgettext(#c, #content, #pl, #count as u64)
-            )
-        } else {
-            quote!(
-                #gettext_call.ngettext(#content, #pl, #count as u64)
-            )
-        }
-    } else {
-        if let Some(c) = message.context {
-            quote!(
-                #gettext_call.pgettext(#c, #content)
-            )
-        } else {
-            quote!(
-                #gettext_call.gettext(#content)
-            )
-        }
-    };
+    output(
+        message
+            .write()
+            .and_then(|_| message.options())
+            .map(|options| {
+                let formatted = message.formatted(&options);
+                message.bind(
+                    None,
+                    quote!(
+                        use gettext_utils::Formatted;
+                        #formatted.to_string()
+                    ),
+                )
+            }),
+    )
+}
 
-    let fargs: syn::punctuated::Punctuated<proc_macro2::TokenStream, Token![,]> = message.format_args.unwrap_or_default().into_iter().map(|x| {
-        quote!(::std::boxed::Box::new(#x))
-    }).collect();
-    let res = quote!({
-        use gettext_utils::try_format;
-        try_format(#gettext_call, &[#fargs]).expect("Error while formatting message")
-    });
-    res.into()
+/// Translates a message and writes it to a given writer, without allocating.
+///
+/// It works like `i18n`, but instead of returning a `String`, the formatted translation
+/// is directly written to anything that has a `write_fmt` method, like `write!` does: a `std::fmt::Formatter`,
+/// a `String`, or any implementor of `std::fmt::Write` or `std::io::Write`. The format arguments
+/// are not boxed either, making it well suited for template engines.
+///
+/// # Return value
+///
+/// The result of `write_fmt`: `std::fmt::Result` or `std::io::Result<()>` depending on the writer.
+///
+/// # Panics
+///
+/// In the same cases as `i18n`. Nothing is written to the writer before the message is checked.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// impl fmt::Display for Greeting {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         i18n_write!(f, self.catalog, "Hello, {}!"; self.name)
+///     }
+/// }
+/// ```
+///
+/// ```rust,ignore
+/// use std::io::Write;
+///
+/// let stdout = std::io::stdout();
+/// i18n_write!(stdout.lock(), cat, "You have one new message", "You have {} new messages"; count)?;
+/// ```
+///
+/// # Syntax
+///
+/// The first argument is the writer, the following ones are the same as for `i18n`.
+/// As for `write!`, you will need to import `std::fmt::Write` or `std::io::Write` if the writer is
+/// not a `Formatter`.
+#[proc_macro]
+pub fn i18n_write(input: TokenStream) -> TokenStream {
+    let call = syn::parse_macro_input!(input as I18nWriteCall);
+    let message = call.message;
+    let writer = call.writer;
+    output(
+        message
+            .write()
+            .and_then(|_| message.options())
+            .map(|options| {
+                let formatted = message.formatted(&options);
+                message.bind(
+                    None,
+                    quote!(
+                        use gettext_utils::Formatted;
+                        (#writer).write_fmt(format_args!("{}", #formatted))
+                    ),
+                )
+            }),
+    )
+}
+
+/// Marks a string as translatable, and gives a value that translates it when displayed.
+///
+/// It works like `i18n`, but the catalog lookup and the formatting only happen when the
+/// returned value is displayed (with `write!`, `format!`, `to_string`, etc.), and the result
+/// is written directly to the output, without intermediate `String`. This is useful for
+/// HTML templates or logging, where the message may not even end up being displayed.
+///
+/// # Return value
+///
+/// A `gettext_utils::LazyMessage`, implementing `std::fmt::Display`. It borrows the catalog and the format arguments,
+/// so they should outlive it.
+///
+/// # Errors
+///
+/// If the translation can't be formatted with the given arguments, displaying the message returns a
+/// `std::fmt::Error` (which will make `to_string` and `format!` panic). If `fallback = true` was given
+/// to `init_i18n`, the original message is displayed instead.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let cat = get_catalog();
+/// let name = "Ana";
+/// let greeting = i18n_lazy!(cat, "Hello, {}!"; name);
+/// log::info!("{}", greeting);
+/// ```
+///
+/// # Syntax
+///
+/// The same as `i18n`. Unlike with `i18n`, the catalog should be something that can be borrowed
+/// (like a variable), and not a temporary value.
+#[proc_macro]
+pub fn i18n_lazy(input: TokenStream) -> TokenStream {
+    let message = syn::parse_macro_input!(input as I18nCall);
+    output(
+        message
+            .write()
+            .and_then(|_| message.options())
+            .map(|options| {
+                let catalog = hygienic("catalog");
+                let catalog_ref = hygienic("catalog_ref");
+                let (gettext_call, original) = message.translation(&catalog_ref);
+                let fargs = message.format_args_array();
+                let fallback = options.fallback;
+                message.bind(
+                    Some(&catalog),
+                    quote!(
+                        ::gettext_utils::LazyMessage::new(
+                            #catalog,
+                            // only captures the bound values, that are references or numbers
+                            move |#catalog_ref| (#gettext_call, #original),
+                            #fargs,
+                            #fallback,
+                        )
+                    ),
+                )
+            }),
+    )
 }
 
 /// This macro configures internationalization for the current crate
@@ -501,6 +1622,38 @@ pub fn i18n(input: TokenStream) -> TokenStream {
 /// This macro should be called before (not in the program flow, but in the Rust parser flow) all other
 /// internationalization macros.
 ///
+/// # Configuration in `Cargo.toml`
+///
+/// Instead of calling this macro, the same options can be given in the `[package.metadata.gettext]` section
+/// of your `Cargo.toml`. The macros then don't depend on the order in which `init_i18n` is expanded, and this
+/// macro should not be called at all. If the package has a library, it is the one that configures the translations:
+/// its binaries, tests and examples use them like when the library calls `init_i18n`.
+///
+/// ```toml
+/// [package.metadata.gettext]
+/// domain = "my_app" # the name of the package by default
+/// langs = ["de", "en", "eo", "fr"]
+/// fallback = true
+///
+/// [package.metadata.gettext.overrides]
+/// gsw = { fallback = "de" }
+/// ```
+///
+/// # Several domains
+///
+/// A crate can have more than one translation domain, for instance to let different teams translate different
+/// parts of it. The first call of this macro gives the main domain of the crate, that `i18n`, `t` and `include_i18n!()`
+/// use by default, and each next call adds another domain, with its own languages and options. The messages of these
+/// domains are chosen with the `domain` argument of `i18n` and `t`, and they are all compiled by `compile_i18n`.
+///
+/// ```rust,ignore
+/// init_i18n!("my_app", de, en, fr);
+/// init_i18n!("my_app_emails", de, fr);
+///
+/// let catalogs = include_i18n!("my_app", "my_app_emails").for_language("fr");
+/// let subject = i18n!(catalogs, domain = "my_app_emails", "Welcome!");
+/// ```
+///
 /// # Examples
 ///
 /// Basic usage:
@@ -514,115 +1667,144 @@ pub fn i18n(input: TokenStream) -> TokenStream {
 /// init_i18n!("my_app", po = false, mo = false, de, en, eo, fr, ja, pl, ru);
 /// ```
 ///
+/// With options for some languages:
+///
+/// ```rust,ignore
+/// init_i18n!("my_app", de, gsw { fallback = de }, fr, ga { plural_forms = "nplurals=3; plural=n==1 ? 0 : n==2 ? 1 : 2;" }, ja { embed = false });
+/// ```
+///
+/// Writing the translations to `locales/my_app/` instead of `po/my_app/`:
+///
+/// ```rust,ignore
+/// init_i18n!("my_app", dir = "locales", de, en, eo, fr, ja, pl, ru);
+/// ```
+///
+/// Using the original message when a translation can't be formatted:
+///
+/// ```rust,ignore
+/// init_i18n!("my_app", fallback = true, de, en, eo, fr, ja, pl, ru);
+/// ```
+///
+/// Parsing each translation only once, for catalogs that live as long as the program:
+///
+/// ```rust,ignore
+/// init_i18n!("my_app", fallback = true, cache = true, de, en, eo, fr, ja, pl, ru);
+/// ```
+///
 /// # Syntax
 ///
 /// This macro expects:
 ///
 /// - a string literal, that is the translation domain of your crate.
+/// - optionally, the `dir` named argument, a string literal, that is the directory where the `.pot` and `.po` files are written
+///   (in a sub-directory for each domain), relative to the `Cargo.toml` of your crate. It is the `po` directory at the root of
+///   your crate (or of its workspace) by default. If the root of the workspace is not detected correctly, it can be given (relative to
+///   the `Cargo.toml` of your crate) with the `GETTEXT_MACROS_ROOT` environment variable, for instance in the `[env]` section of
+///   `.cargo/config.toml`.
 /// - optionally, the `po` named argument, that is a boolean literal to turn off `.po` generation from `.pot` in `compile_i18n`
 /// - optionally, the `mo` named argument, that is a boolean literal too, to turn of `.po` compilation into `.mo` files in `compile_i18n`.
 ///   Note that if you turn this feature off, `include_i18n` won't work unless you manually generate the `.mo` files in
 ///   `target/TARGET/gettext_macros/LOCALE/DOMAIN.mo`.
+/// - optionally, the `fallback` named argument, a boolean too, to make `i18n` use the original message instead of panicking
+///   when a translation can't be formatted. The failure is reported with the hook registered with `gettext_utils::set_error_hook`.
+/// - optionally, the `cache` named argument, a boolean too, to make `i18n` and `i18n_write` parse each translation only once.
+///   The catalogs you use with these macros then need to be `'static`. Only the messages that are string literals are cached.
+/// - optionally, the `compiled` named argument, a boolean too, to make `include_i18n` compile the `.po` files to Rust code,
+///   instead of embedding the `.mo` files and parsing them at runtime. The catalogs are then `gettext_utils::StaticCatalog`s.
+/// - optionally, the `reload` named argument, a boolean too, to make `include_i18n` load the `.po` files from the disk at runtime
+///   in debug builds, and reload them when they change, so that translations can be tested without recompiling. The files that can't be
+///   loaded are reported with the hook registered with `gettext_utils::set_error_hook`. Release builds still embed the `.mo` files.
+///   This option has no effect when `compiled` is turned on.
+/// - optionally, the `compress` named argument, a boolean too, to make `include_i18n` embed the `.mo` files compressed, to produce smaller
+///   binaries. Each catalog is then decompressed the first time it is used. This option needs the `compress` feature of both
+///   `gettext-macros` and `gettext-utils`, and has no effect when `compiled` is turned on.
 /// - optionally, the `location` named argument, a boolean too, to avoid writing the location of the string in the source code to translation files.
 ///   Having this location available can be usefull if your translators know a bit of Rust and needs context about what they are translating, but it
 ///   also makes bigger diffs, because your `.pot` and `.po` files may be regenerated if a line number changes.
-/// - then, the list of languages you want your app to be translated in, separated by commas. The languages are not string literals, but identifiers.
+/// - then, the list of languages you want your app to be translated in, separated by commas. The languages can be identifiers (`fr`, `pt_BR`),
+///   or string literals for the ones that are not valid identifiers (`"pt-BR"`, `"zh-Hant"`, `"sr@latin"`). They should be BCP 47 language tags
+///   or POSIX locales, and are always converted to POSIX locales (`pt_BR`, `zh_Hant`, `sr@latin`): this is the name of their `.po` files,
+///   and of their catalogs in `include_i18n` (where they can still be looked up with `pt-BR`).
+/// - each language can be followed by a block of options, between braces and separated by commas:
+///   - `plural_forms`, a string literal, that replaces the `Plural-Forms` header of its `.po` file (for the languages `msginit` doesn't know,
+///     or to use other rules), like `"nplurals=2; plural=(n != 1);"`.
+///   - `fallback`, another language of the list, whose translations are used for the messages that are not translated in this
+///     one (and whose plural forms are used if this language doesn't have valid ones). It is applied when the `.mo` files are generated,
+///     or when the catalogs are compiled, but not when the `.po` files are reloaded at runtime.
+///   - `embed`, a boolean, to keep a language out of `include_i18n` (its `.po` and `.mo` files are still generated), for instance
+///     while it is being translated. It can still be embedded with the `GETTEXT_MACROS_LANGS` environment variable.
 ///
-/// All the three boolean options are turned on by default. Also note that you may ommit one (or more) of them, but they should always be in this order.
+/// The `po`, `mo` and `location` options are turned on by default, while `fallback`, `cache`, `compiled`, `reload`
+/// and `compress` are turned off. Also note that you may ommit one (or more) of them, and that they can be given in any
+/// order. Their values should be `true` or `false`.
 #[proc_macro]
 pub fn init_i18n(input: TokenStream) -> TokenStream {
-    let input = proc_macro2::TokenStream::from(input);
-    let mut input = input.into_iter();
-    let domain = match input.next() {
-        Some(TokenTree::Literal(lit)) => lit.to_string().replace("\"", ""),
-        Some(_) => panic!("Domain should be a str"),
-        None => panic!("Expected a translation domain (for instance \"myapp\")"),
-    };
-
-    let (po, mo) = if let Some(n) = input.next() {
-        if is(&n, ',') {
-            let po = named_arg(input.clone(), "po");
-            if let Some(po) = po.clone() {
-                for _ in 0..(po.into_iter().count() + 3) {
-                    input.next();
-                }
-            }
+    let res = syn::parse::<InitI18nCall>(input).and_then(init_config);
+    // the other macros can still be used if it only failed to add another domain
+    if res.is_err() && Config::read().is_err() {
+        let _ = session(|s| s.init_failed = true);
+    }
+    output(res.map(|_| {
+        quote!(
+            // makes the crate recompile when the root of the workspace is changed
+            const _: ::std::option::Option<&str> = option_env!(#ROOT_VAR);
+        )
+    }))
+}
 
-            let mo = named_arg(input.clone(), "mo");
-            if let Some(mo) = mo.clone() {
-                for _ in 0..(mo.into_iter().count() + 3) {
-                    input.next();
-                }
-            }
+/// Writes the configuration given to `init_i18n`.
+fn init_config(args: InitI18nCall) -> syn::Result<()> {
+    if session(|s| s.init_failed)? {
+        return Err(error(
+            "the translations of this crate are not configured, because of the error in init_i18n!",
+        ));
+    }
+    let domain = args.domain.value();
+    // the first domain of the crate is the main one, and the next calls add other domains
+    let metadata = session(|s| s.metadata.clone())?;
+    if metadata.is_some() {
+        Config::read()?;
+    }
+    if metadata.is_some_and(|conf| conf.domain == domain) {
+        return Err(syn::Error::new(
+            args.domain.span(),
+            "this domain is already configured in [package.metadata.gettext], remove this init_i18n! call (or this section)",
+        ));
+    }
+    let initialized = session(|s| s.initialized.clone())?;
+    let secondary = !initialized.is_empty();
+    if initialized.contains(&domain) {
+        return Err(syn::Error::new(
+            args.domain.span(),
+            "init_i18n! should only be called once per domain, and this one is already configured",
+        ));
+    }
 
-            (po, mo)
-        } else {
-            (None, None)
-        }
-    } else {
-        (None, None)
+    let option = |name: &str, default: bool| {
+        args.options
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.value)
+            .unwrap_or(default)
     };
 
-    let mut langs = vec![];
-    match input.next() {
-        Some(TokenTree::Ident(i)) => {
-            langs.push(i.to_string());
-            loop {
-                let next = input.next();
-                if next.is_none() || !is(&next.expect("Unreachable: next should be Some"), ',') {
-                    break;
-                }
-                match input.next() {
-                    Some(TokenTree::Ident(i)) => {
-                        langs.push(i.to_string());
-                    }
-                    _ => panic!("Expected a language identifier"),
-                }
-            }
-        }
-        None => {}
-        _ => panic!("Expected a language identifier"),
-    }
-
     let conf = Config {
-        domain: domain.clone(),
-        make_po: po.map(|x| x.to_string() == "true").unwrap_or(true),
-        make_mo: mo.map(|x| x.to_string() == "true").unwrap_or(true),
-        langs,
+        version: CONFIG_VERSION,
+        target: target()?,
+        secondary,
+        domain,
+        po_dir: po_dir(args.dir.as_ref().map(|dir| dir.value()))?,
+        make_po: option("po", true),
+        make_mo: option("mo", true),
+        fallback: option("fallback", false),
+        cache: option("cache", false),
+        compiled: option("compiled", false),
+        reload: option("reload", false),
+        compress: option("compress", false),
+        langs: args.langs,
+        overrides: args.overrides,
     };
-    conf.write();
-
-    // write base .pot
-    create_dir_all(format!("po/{}", domain)).expect("Couldn't create po dir");
-    let mut pot = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(format!("po/{0}/{0}.pot", domain))
-        .expect("Couldn't open .pot file");
-    pot.write_all(
-        &format!(
-            r#"msgid ""
-msgstr ""
-"Project-Id-Version: {}\n"
-"Report-Msgid-Bugs-To: \n"
-"POT-Creation-Date: 2018-06-15 16:33-0700\n"
-"PO-Revision-Date: YEAR-MO-DA HO:MI+ZONE\n"
-"Last-Translator: FULL NAME <EMAIL@ADDRESS>\n"
-"Language-Team: LANGUAGE <LL@li.org>\n"
-"Language: \n"
-"MIME-Version: 1.0\n"
-"Content-Type: text/plain; charset=UTF-8\n"
-"Content-Transfer-Encoding: 8bit\n"
-"Plural-Forms: nplurals=INTEGER; plural=EXPRESSION;\n"
-"#,
-            domain
-        )
-        .into_bytes(),
-    )
-    .expect("Couldn't init .pot file");
-
-    quote!().into()
+    conf.init()
 }
 
 /// Gives you the translation domain for the current crate.
@@ -638,9 +1820,10 @@ msgstr ""
 /// ```
 #[proc_macro]
 pub fn i18n_domain(_: TokenStream) -> TokenStream {
-    let domain = Config::read().domain;
-    let tok = TokenTree::Literal(Literal::string(&domain));
-    quote!(#tok).into()
+    output(Config::read().map(|conf| {
+        let tok = TokenTree::Literal(Literal::string(&conf.domain));
+        quote!(#tok)
+    }))
 }
 
 /// Compiles your internationalization files.
@@ -659,99 +1842,190 @@ pub fn i18n_domain(_: TokenStream) -> TokenStream {
 /// ```
 #[proc_macro]
 pub fn compile_i18n(_: TokenStream) -> TokenStream {
-    let conf = Config::read();
+    output(compile_domains().map(|_| quote!()))
+}
+
+/// Compiles the translations of the current crate, and of the other domains it added messages to.
+fn compile_domains() -> syn::Result<()> {
+    let conf = Config::read()?;
+    if session(|s| s.compiled)? {
+        return Err(error("compile_i18n! should only be called once per crate"));
+    }
+    compile_domain(&conf)?;
+    for domain in Config::extra_domains(&conf.target)? {
+        compile_domain(&Config::find(&domain)?)?;
+    }
+    // lets the other macros know that the translations are up to date
+    session(|s| s.compiled = true)?;
+    Ok(())
+}
+
+/// Runs one of the gettext tools, and reports its errors.
+fn run(command: &mut Command) -> syn::Result<()> {
+    let tool = command.get_program().to_string_lossy().into_owned();
+    let out = command.stdout(Stdio::null()).output().map_err(|e| {
+        error(format!(
+            "Couldn't run {}: {}. Make sure gettext is installed.",
+            tool, e
+        ))
+    })?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(error(format!(
+            "{} failed ({}): {}",
+            tool,
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )))
+    }
+}
+
+/// Updates the `.po` files of a domain from its `.pot` file, and compiles them to `.mo` files.
+fn compile_domain(conf: &Config) -> syn::Result<()> {
     let domain = &conf.domain;
 
-    let pot_path = root_crate_path()
-        .join("po")
-        .join(domain.clone())
-        .join(format!("{}.pot", domain));
+    let pot_path = conf.domain_dir().join(format!("{}.pot", domain));
 
-    for lang in conf.langs {
-        let po_path = root_crate_path()
-            .join("po")
-            .join(domain.clone())
-            .join(format!("{}.po", lang.clone()));
+    for lang in &conf.langs {
+        let po_path = conf.domain_dir().join(format!("{}.po", lang.clone()));
+        let options = conf.options(lang);
         if conf.make_po {
             if po_path.exists() && po_path.is_file() {
                 // Update it
-                Command::new("msgmerge")
+                run(Command::new("msgmerge")
                     .arg("-U")
-                    .arg(po_path.to_str().expect("msgmerge: PO path error"))
-                    .arg(pot_path.to_str().expect("msgmerge: POT path error"))
-                    .stdout(Stdio::null())
-                    .status()
-                    .map(|s| {
-                        if !s.success() {
-                            panic!("Couldn't update PO file")
-                        }
-                    })
-                    .expect("Couldn't update PO file. Make sure msgmerge is installed.");
+                    .arg(&po_path)
+                    .arg(&pot_path))?;
             } else {
                 println!("Creating {}", lang.clone());
                 // Create it from the template
-                Command::new("msginit")
-                    .arg(format!(
-                        "--input={}",
-                        pot_path.to_str().expect("msginit: POT path error")
-                    ))
-                    .arg(format!(
-                        "--output-file={}",
-                        po_path.to_str().expect("msginit: PO path error")
-                    ))
+                run(Command::new("msginit")
+                    .arg("-i")
+                    .arg(&pot_path)
+                    .arg("-o")
+                    .arg(&po_path)
                     .arg("-l")
                     .arg(lang.clone())
-                    .arg("--no-translator")
-                    .stdout(Stdio::null())
-                    .status()
-                    .map(|s| {
-                        if !s.success() {
-                            panic!("Couldn't init PO file (gettext returned an error)")
-                        }
-                    })
-                    .expect("Couldn't init PO file. Make sure msginit is installed.");
+                    .arg("--no-translator"))?;
+            }
+            if let Some(ref forms) = options.plural_forms {
+                set_plural_forms(&po_path, forms)?;
             }
         }
+    }
 
+    // the .po files are all updated first, because they may be used as fallbacks
+    for lang in &conf.langs {
+        let po_path = conf.domain_dir().join(format!("{}.po", lang.clone()));
         if conf.make_mo {
             if !po_path.exists() {
-                panic!(
-                    "{} doesn't exist. Make sure you didn't disabled po generation.",
+                return Err(error(format!(
+                    "{} doesn't exist: turn po generation back on, or create this file yourself",
                     po_path.display()
-                );
+                )));
             }
 
             // Generate .mo
-            let mo_dir = Path::new(&env::var("CARGO_TARGET_DIR").unwrap_or_else(|_| {
-                root_crate_path()
-                    .join("target")
-                    .join("debug")
-                    .to_str()
-                    .expect("Couldn't compute mo output dir")
-                    .into()
-            }))
-            .join("gettext_macros")
-            .join(lang);
-            create_dir_all(mo_dir.clone()).expect("Couldn't create MO directory");
+            let mo_dir = Config::dir()?.join(lang);
+            create_dir_all(&mo_dir)
+                .map_err(|e| error(format!("Couldn't create {}: {}", mo_dir.display(), e)))?;
             let mo_path = mo_dir.join(format!("{}.mo", domain));
 
-            Command::new("msgfmt")
-                .arg(format!(
-                    "--output-file={}",
-                    mo_path.to_str().expect("msgfmt: MO path error")
-                ))
-                .arg(po_path)
-                .stdout(Stdio::null())
-                .status()
-                .map(|s| {
-                    if !s.success() {
-                        panic!("Couldn't compile translations (gettext returned an error)")
-                    }
-                })
-                .expect("Couldn't compile translations. Make sure msgfmt is installed");
+            if conf.options(lang).fallback.is_some() {
+                let mo = merged_po(conf, lang)?.to_mo();
+                std::fs::write(&mo_path, mo)
+                    .map_err(|e| error(format!("Couldn't write {}: {}", mo_path.display(), e)))?;
+            } else {
+                run(Command::new("msgfmt").arg("-o").arg(mo_path).arg(po_path))?;
+            }
         }
     }
-    quote!().into()
+    Ok(())
+}
+
+/// Replaces the `Plural-Forms` header of a `.po` file.
+fn set_plural_forms(path: &Path, forms: &str) -> syn::Result<()> {
+    let content = read_to_string(path)
+        .map_err(|e| error(format!("Couldn't read {}: {}", path.display(), e)))?;
+    let header = format!("\"Plural-Forms: {}\\n\"", forms);
+    let mut lines = content.lines().map(String::from).collect::<Vec<_>>();
+    match lines.iter().position(|l| l.starts_with("\"Plural-Forms:")) {
+        Some(i) => lines[i] = header,
+        None => {
+            let i = lines
+                .iter()
+                .position(|l| l.trim() == "msgstr \"\"")
+                .map_or(0, |i| i + 1);
+            lines.insert(i, header);
+        }
+    }
+    let updated = lines.join("\n") + "\n";
+    // the file is not touched if it is up to date, not to trigger a reload
+    if updated != content {
+        std::fs::write(path, updated)
+            .map_err(|e| error(format!("Couldn't write {}: {}", path.display(), e)))?;
+    }
+    Ok(())
+}
+
+/// Reads the `.po` file of a language, with the translations of its fallback language for the messages
+/// it doesn't translate.
+fn merged_po(conf: &Config, lang: &str) -> syn::Result<gettext_utils::po::Po> {
+    let read = |lang: &str| {
+        let path = conf.domain_dir().join(format!("{}.po", lang));
+        let content = std::fs::read_to_string(&path).map_err(|e| {
+            error(format!(
+                "Couldn't read {}: {}. Call compile_i18n! before include_i18n!.",
+                path.display(),
+                e
+            ))
+        })?;
+        gettext_utils::po::Po::parse(&content)
+            .map_err(|e| error(format!("Couldn't parse {}: {}", path.display(), e)))
+    };
+    let mut po = read(lang)?;
+    if let Some(fallback) = conf.options(lang).fallback {
+        let fallback = read(&fallback)?;
+        // msginit doesn't know the plural forms of every language, and the ones of the fallback are used then
+        let valid_plural = po
+            .plural
+            .as_deref()
+            .map(|p| plural::compile_plural(p).is_ok())
+            .unwrap_or(false);
+        if !valid_plural {
+            let forms = |header: &Option<String>| {
+                header
+                    .as_deref()
+                    .and_then(|h| h.lines().find(|l| l.starts_with("Plural-Forms:")))
+                    .map(String::from)
+            };
+            if let (Some(header), Some(fallback_forms)) =
+                (po.header.as_mut(), forms(&fallback.header))
+            {
+                *header = header
+                    .lines()
+                    .filter(|l| !l.starts_with("Plural-Forms:"))
+                    .chain(std::iter::once(fallback_forms.as_str()))
+                    .map(|l| format!("{}\n", l))
+                    .collect();
+                po.plural = fallback.plural.clone();
+            }
+        }
+        let missing = fallback
+            .entries
+            .into_iter()
+            .filter(|f| {
+                !po.entries
+                    .iter()
+                    .any(|e| e.context == f.context && e.id == f.id)
+            })
+            .collect::<Vec<_>>();
+        po.entries.extend(missing);
+        po.entries
+            .sort_by(|a, b| (&a.context, &a.id).cmp(&(&b.context, &b.id)));
+    }
+    Ok(po)
 }
 
 /// Use this macro to staticaly import translations into your final binary.
@@ -759,38 +2033,267 @@ pub fn compile_i18n(_: TokenStream) -> TokenStream {
 /// This macro won't work if ou set `mo = false` in `init_i18n`, unless you manually generate the `.mo` files in
 /// `target/TARGET/gettext_macros/LOCALE/DOMAIN.mo`.
 ///
+/// # Return value
+///
+/// A `&'static gettext_utils::Catalogs<gettext_utils::LazyCatalog<gettext::Catalog>>`, containing the catalog of
+/// each language listed in `init_i18n`. The default language is the first one of this list.
+///
+/// The catalogs are stored in a `static`, and each of them is only parsed the first time it is used. Since this
+/// `static` is specific to each call of this macro, you should only call it once, in a function that you use
+/// everywhere you need catalogs.
+///
+/// If `compiled = true` was given to `init_i18n`, the `.po` files are compiled to Rust code instead,
+/// and this macro gives a `&'static gettext_utils::Catalogs<gettext_utils::StaticCatalog>`. Nothing is parsed
+/// at runtime, and the `gettext` crate is not used. In that case, `mo = false` can be used too.
+///
+/// The languages to embed can be restricted at build time, without changing the call to `init_i18n`, by setting
+/// the `GETTEXT_MACROS_LANGS` environment variable to a comma-separated list of languages (for instance
+/// `GETTEXT_MACROS_LANGS=fr,en cargo build --release`). They keep the order of `init_i18n`, so the default
+/// language is the first of them in this list.
+///
+/// If `reload = true` was given to `init_i18n`, debug builds don't embed anything: the catalogs are loaded
+/// from the `.po` files of the translations directory at runtime, and reloaded when these files change (at most
+/// once per second). Release builds are not affected.
+///
+/// # Translations of other crates
+///
+/// This macro can also be given a list of crate names or translation domains, as string literals, to embed the
+/// translations of other crates of your workspace, that called `init_i18n` with their own domain. It then gives a
+/// `&'static gettext_utils::Domains<...>` instead, where the catalogs can be looked up by domain and language.
+/// These crates should be compiled before the current one (they usually are its dependencies), and if the
+/// translations of one of them are compiled to Rust code, they should all be.
+///
+/// ```rust,ignore
+/// let domains = include_i18n!("my_app", "my_lib");
+/// let catalog = domains.get_or_default("my_lib", "eo").unwrap();
+/// ```
+///
 /// # Example
 ///
 /// ```rust,ignore
 /// let catalogs = include_i18n!();
-/// catalog.into_iter()
-///     .find(|(lang, _)| lang == "eo")
-///     .map(|(_, catalog| println!("{}", i18n!(catalog, "Hello world!")));
+/// let catalog = catalogs.get_or_default("eo").unwrap();
+/// println!("{}", i18n!(catalog, "Hello world!"));
+///
+/// for lang in catalogs.languages() {
+///     println!("Available language: {}", lang);
+/// }
 /// ```
 #[proc_macro]
-pub fn include_i18n(_: TokenStream) -> TokenStream {
-    let conf = Config::read();
-    let locales = conf.langs.clone().into_iter().map(|l| {
-        let lang = TokenTree::Literal(Literal::string(&l));
-        let path = Config::path().parent().unwrap().join(l).join(format!("{}.mo", conf.domain));
-
-        if !path.exists() {
-            panic!("{} doesn't exist. Make sure to call compile_i18n! before include_i18n!, and check that you didn't disabled mo compilation.", path.display());
-        }
-
-        let path = TokenTree::Literal(Literal::string(path.to_str().expect("Couldn't write MO file path")));
-        quote!{
-            (#lang, ::gettext::Catalog::parse(
-                &include_bytes!(
-                    #path
-                )[..]
-            ).expect("Error while loading catalog")),
-        }
-	}).collect::<proc_macro2::TokenStream>();
-
-    quote!({
-        vec![
-            #locales
-        ]
-    }).into()
+pub fn include_i18n(input: TokenStream) -> TokenStream {
+    let names = syn::parse_macro_input!(input with syn::punctuated::Punctuated::<syn::LitStr, Token![,]>::parse_terminated);
+    output(include_catalogs(names.into_iter().collect()))
+}
+
+fn include_catalogs(names: Vec<syn::LitStr>) -> syn::Result<proc_macro2::TokenStream> {
+    if names.is_empty() {
+        let mut conf = [Config::read()?];
+        embedded_langs(&mut conf)?;
+        let (catalog_type, catalogs) = catalogs(&conf[0])?;
+        return Ok(quote!({
+            // makes the crate recompile when the list of embedded languages changes
+            const _: ::std::option::Option<&str> = option_env!(#LANGS_VAR);
+            static CATALOGS: ::std::sync::OnceLock<
+                ::gettext_utils::Catalogs<#catalog_type>
+            > = ::std::sync::OnceLock::new();
+            CATALOGS.get_or_init(|| #catalogs)
+        }));
+    }
+
+    let mut confs = names
+        .iter()
+        .map(|name| Config::find(&name.value()).map_err(|e| syn::Error::new(name.span(), e)))
+        .collect::<syn::Result<Vec<_>>>()?;
+    embedded_langs(&mut confs)?;
+    if let Some((name, _)) = names
+        .iter()
+        .zip(&confs)
+        .find(|(_, conf)| conf.compiled != confs[0].compiled)
+    {
+        return Err(syn::Error::new(
+            name.span(),
+            "the catalogs of all these crates should be compiled (or not) to be included together: use the same `compiled` option for all of them",
+        ));
+    }
+    let catalog_type = catalogs(&confs[0])?.0;
+    let domains = confs
+        .iter()
+        .map(|conf| {
+            let domain = &conf.domain;
+            let catalogs = catalogs(conf)?.1;
+            Ok(quote!((#domain, #catalogs)))
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(quote!({
+        // makes the crate recompile when the list of embedded languages changes
+        const _: ::std::option::Option<&str> = option_env!(#LANGS_VAR);
+        static DOMAINS: ::std::sync::OnceLock<
+            ::gettext_utils::Domains<#catalog_type>
+        > = ::std::sync::OnceLock::new();
+        DOMAINS.get_or_init(|| ::gettext_utils::Domains::new(vec![#(#domains),*]))
+    }))
+}
+
+/// Generates the catalogs of a crate, and gives their type.
+fn catalogs(conf: &Config) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+    // the configurations of other targets come from previous compilations, where they were compiled
+    let current = conf.target == target()?;
+    if current && (conf.make_po || conf.make_mo) && !session(|s| s.compiled)? {
+        return Err(error(format!(
+            "compile_i18n! should be called before include_i18n!, otherwise the translations would be missing or outdated ({})",
+            ORDER_HELP
+        )));
+    }
+    if conf.compiled {
+        return Ok((
+            quote!(::gettext_utils::StaticCatalog),
+            compiled_catalogs(conf)?,
+        ));
+    }
+    let locales = conf.langs.iter().map(|l| {
+        let path = Config::dir()?.join(l).join(format!("{}.mo", conf.domain));
+
+        if (!conf.reload || conf.compress) && !path.exists() {
+            return Err(error(format!(
+                "{} doesn't exist: call compile_i18n! before include_i18n!, and make sure that mo compilation is not disabled",
+                path.display()
+            )));
+        }
+
+        let parse = quote!(|data| ::gettext::Catalog::parse(data).expect("Error while loading catalog"));
+        let embedded = if conf.compress {
+            compressed_catalog(&path, &parse)?
+        } else {
+            let path = path_str(&path)?;
+            quote!(::gettext_utils::LazyCatalog::new(include_bytes!(#path), #parse))
+        };
+        if conf.reload {
+            let po_path = conf.domain_dir()
+                .join(format!("{}.po", l));
+            let po_path = path_str(&po_path)?;
+            Ok(quote!{
+                (#l, {
+                    #[cfg(debug_assertions)]
+                    let catalog = ::gettext_utils::LazyCatalog::from_po_file(#po_path, #parse);
+                    #[cfg(not(debug_assertions))]
+                    let catalog = #embedded;
+                    catalog
+                })
+            })
+        } else {
+            Ok(quote!((#l, #embedded)))
+        }
+    }).collect::<syn::Result<Vec<_>>>()?;
+
+    Ok((
+        quote!(::gettext_utils::LazyCatalog<::gettext::Catalog>),
+        quote!(::gettext_utils::Catalogs::new(vec![#(#locales),*])),
+    ))
+}
+
+const COMPRESS_FEATURE_NEEDED: &str =
+    "compress = true needs the `compress` feature of gettext-macros: enable it in your Cargo.toml (along with the one of gettext-utils)";
+
+/// Embeds the `.mo` file at `path` compressed, in a `LazyCatalog`.
+#[cfg(feature = "compress")]
+fn compressed_catalog(
+    path: &Path,
+    parse: &proc_macro2::TokenStream,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let data = std::fs::read(path)
+        .map_err(|e| error(format!("Couldn't read {}: {}", path.display(), e)))?;
+    let data = Literal::byte_string(&miniz_oxide::deflate::compress_to_vec(&data, 10));
+    let path = path_str(path)?;
+    Ok(quote!({
+        // makes the crate recompile when the translations change
+        const _: &[u8] = include_bytes!(#path);
+        ::gettext_utils::LazyCatalog::compressed(#data, #parse)
+    }))
+}
+
+#[cfg(not(feature = "compress"))]
+fn compressed_catalog(
+    _: &Path,
+    _: &proc_macro2::TokenStream,
+) -> syn::Result<proc_macro2::TokenStream> {
+    Err(error(COMPRESS_FEATURE_NEEDED))
+}
+
+/// The environment variable that restricts the languages embedded by `include_i18n`.
+const LANGS_VAR: &str = "GETTEXT_MACROS_LANGS";
+
+/// Filters the languages given to `init_i18n` with the `GETTEXT_MACROS_LANGS` environment variable, if it is set,
+/// or removes the ones that should not be embedded otherwise.
+fn embedded_langs(confs: &mut [Config]) -> syn::Result<()> {
+    let selected = match env::var(LANGS_VAR) {
+        Ok(ref selected) if !selected.trim().is_empty() => selected
+            .split(',')
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| normalize_lang(l).map_err(|e| error(format!("Invalid {}: {}", LANGS_VAR, e))))
+            .collect::<syn::Result<Vec<_>>>()?,
+        _ => {
+            for conf in confs {
+                let overrides = &conf.overrides;
+                conf.langs
+                    .retain(|l| overrides.get(l).map(|o| o.embed).unwrap_or(true));
+            }
+            return Ok(());
+        }
+    };
+    for lang in &selected {
+        if !confs.iter().any(|conf| conf.langs.contains(lang)) {
+            return Err(error(format!(
+                "{} contains {}, that is not a language listed in init_i18n!: add it there, or remove it from {}",
+                LANGS_VAR, lang, LANGS_VAR
+            )));
+        }
+    }
+    for conf in confs {
+        conf.langs.retain(|l| selected.contains(l));
+    }
+    Ok(())
+}
+
+fn compiled_catalogs(conf: &Config) -> syn::Result<proc_macro2::TokenStream> {
+    let locales = conf
+        .langs
+        .iter()
+        .map(|lang| {
+            let path = conf.domain_dir().join(format!("{}.po", lang));
+            let catalog = merged_po(conf, lang)?;
+            let plural = plural::compile_plural(catalog.plural.as_deref().unwrap_or("n != 1"))
+                .map_err(|e| error(format!("Invalid plural forms in {}: {}", path.display(), e)))?;
+            let messages = catalog.entries.iter().map(|entry| {
+                let context = match entry.context {
+                    Some(ref c) => quote!(::std::option::Option::Some(#c)),
+                    None => quote!(::std::option::Option::None),
+                };
+                let id = &entry.id;
+                let translations = &entry.translations;
+                quote!((#context, #id, &[#(#translations),*]))
+            });
+            let sources = std::iter::once(lang)
+                .chain(conf.options(lang).fallback.as_ref())
+                .map(|l| conf.domain_dir().join(format!("{}.po", l)))
+                .collect::<Vec<_>>();
+            let sources = sources
+                .iter()
+                .map(|p| path_str(p))
+                .collect::<syn::Result<Vec<_>>>()?;
+            Ok(quote! {
+                (#lang, {
+                    // makes the crate recompile when the translations change
+                    #(const _: &[u8] = include_bytes!(#sources);)*
+                    static MESSAGES: &[::gettext_utils::StaticMessage] = &[#(#messages),*];
+                    fn plural(n: u64) -> usize {
+                        (#plural) as usize
+                    }
+                    ::gettext_utils::StaticCatalog::new(MESSAGES, plural)
+                })
+            })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    Ok(quote!(::gettext_utils::Catalogs::new(vec![#(#locales),*])))
 }
@@ -0,0 +1,161 @@
+//! Compilation of the `Plural-Forms` expressions of `.po` files to Rust code.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// A parser for the C expressions of the `Plural-Forms` header, that compiles them to Rust.
+///
+/// Every sub-expression is compiled to a `u64`, like in C where booleans are integers.
+struct PluralParser<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> PluralParser<'a> {
+    fn tokenize(expr: &'a str) -> Result<Vec<&'a str>, String> {
+        let mut tokens = vec![];
+        let mut rest = expr.trim_end_matches(';').trim();
+        while !rest.is_empty() {
+            let len = if rest.starts_with(|c: char| c.is_ascii_digit()) {
+                rest.find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len())
+            } else if ["==", "!=", "<=", ">=", "&&", "||"]
+                .iter()
+                .any(|op| rest.starts_with(op))
+            {
+                2
+            } else if rest.starts_with(|c: char| "n?:<>+-*/%!()".contains(c)) {
+                1
+            } else {
+                return Err(format!("unexpected character in plural forms: `{}`", rest));
+            };
+            tokens.push(&rest[..len]);
+            rest = rest[len..].trim_start();
+        }
+        Ok(tokens)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected `{}` in plural forms", token))
+        }
+    }
+
+    fn ternary(&mut self) -> Result<TokenStream, String> {
+        let cond = self.binary(0)?;
+        if self.peek() == Some("?") {
+            self.pos += 1;
+            let yes = self.ternary()?;
+            self.expect(":")?;
+            let no = self.ternary()?;
+            Ok(quote!((if #cond != 0 { #yes } else { #no })))
+        } else {
+            Ok(cond)
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<TokenStream, String> {
+        const LEVELS: &[&[&str]] = &[
+            &["||"],
+            &["&&"],
+            &["==", "!="],
+            &["<", ">", "<=", ">="],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = self.peek().filter(|t| LEVELS[level].contains(t)) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = match op {
+                "||" => quote!(((#lhs != 0 || #rhs != 0) as u64)),
+                "&&" => quote!(((#lhs != 0 && #rhs != 0) as u64)),
+                "==" => quote!(((#lhs == #rhs) as u64)),
+                "!=" => quote!(((#lhs != #rhs) as u64)),
+                "<" => quote!(((#lhs < #rhs) as u64)),
+                ">" => quote!(((#lhs > #rhs) as u64)),
+                "<=" => quote!(((#lhs <= #rhs) as u64)),
+                ">=" => quote!(((#lhs >= #rhs) as u64)),
+                "+" => quote!((#lhs).wrapping_add(#rhs)),
+                "-" => quote!((#lhs).wrapping_sub(#rhs)),
+                "*" => quote!((#lhs).wrapping_mul(#rhs)),
+                "/" => quote!((#lhs).checked_div(#rhs).unwrap_or(0)),
+                _ => quote!((#lhs).checked_rem(#rhs).unwrap_or(0)),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<TokenStream, String> {
+        match self.peek() {
+            Some("!") => {
+                self.pos += 1;
+                let expr = self.unary()?;
+                Ok(quote!(((#expr == 0) as u64)))
+            }
+            Some("(") => {
+                self.pos += 1;
+                let expr = self.ternary()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some("n") => {
+                self.pos += 1;
+                Ok(quote!(n))
+            }
+            Some(num) if num.starts_with(|c: char| c.is_ascii_digit()) => {
+                self.pos += 1;
+                let num: u64 = num
+                    .parse()
+                    .map_err(|_| format!("number too big in plural forms: {}", num))?;
+                Ok(quote!(#num))
+            }
+            Some(tok) => Err(format!("unexpected `{}` in plural forms", tok)),
+            None => Err("unexpected end of plural forms".into()),
+        }
+    }
+}
+
+/// Compiles the expression of a `Plural-Forms` header to a Rust expression of type `u64`,
+/// using the `n: u64` variable.
+pub fn compile_plural(expr: &str) -> Result<TokenStream, String> {
+    let mut parser = PluralParser {
+        tokens: PluralParser::tokenize(expr)?,
+        pos: 0,
+    };
+    let res = parser.ternary()?;
+    match parser.peek() {
+        Some(tok) => Err(format!("unexpected `{}` in plural forms", tok)),
+        None => Ok(res),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plural_test() {
+        assert_eq!(
+            compile_plural("n != 1").unwrap().to_string(),
+            quote!(((n != 1u64) as u64)).to_string()
+        );
+        assert!(compile_plural(
+            "(n%10==1 && n%100!=11 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);"
+        )
+        .is_ok());
+        assert!(compile_plural("n ? 1").is_err());
+        assert!(compile_plural("n + x").is_err());
+        assert!(compile_plural("(n").is_err());
+    }
+}
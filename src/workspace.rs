@@ -0,0 +1,194 @@
+//! Detection of the workspace of a crate, as cargo does it.
+
+use std::path::{Path, PathBuf};
+
+fn read_manifest(path: &Path) -> Result<toml::Value, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+    content
+        .parse()
+        .map_err(|e| format!("Couldn't parse {}: {}", path.display(), e))
+}
+
+/// Matches a file name with a pattern that may contain `*` and `?` wildcards.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    match pattern.chars().next() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len())
+            .filter(|i| name.is_char_boundary(*i))
+            .any(|i| wildcard_match(&pattern[1..], &name[i..])),
+        Some('?') => name
+            .chars()
+            .next()
+            .is_some_and(|c| wildcard_match(&pattern[1..], &name[c.len_utf8()..])),
+        Some(p) => {
+            name.starts_with(p) && wildcard_match(&pattern[p.len_utf8()..], &name[p.len_utf8()..])
+        }
+    }
+}
+
+/// Matches a path, relative to the root of the workspace, with one of its `members` (that may contain wildcards).
+fn member_match(pattern: &str, path: &Path) -> bool {
+    let pattern = pattern
+        .split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect::<Vec<_>>();
+    let components = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+    pattern.len() == components.len()
+        && pattern
+            .iter()
+            .zip(&components)
+            .all(|(p, c)| wildcard_match(p, c))
+}
+
+/// Tells if the crate at `path` (relative to the root of the workspace) is a member of the workspace described by `manifest`.
+fn is_member(manifest: &toml::Value, root: &Path, path: &Path) -> bool {
+    let workspace = match manifest.get("workspace") {
+        Some(workspace) => workspace,
+        None => return false,
+    };
+    let list = |key: &str| {
+        workspace
+            .get(key)
+            .and_then(|l| l.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|p| p.as_str())
+    };
+    if list("exclude").any(|excluded| path.starts_with(excluded)) {
+        return false;
+    }
+    // the path dependencies of the root package are members too
+    let path_dependency = ["dependencies", "dev-dependencies", "build-dependencies"]
+        .iter()
+        .filter_map(|key| manifest.get(key).and_then(|deps| deps.as_table()))
+        .flat_map(|deps| deps.values())
+        .filter_map(|dep| dep.get("path").and_then(|p| p.as_str()))
+        .any(|dep| normalize(&root.join(dep)) == root.join(path));
+    path_dependency || list("members").any(|member| member_match(member, path))
+}
+
+/// Removes the `.` and `..` components of a path, without resolving symbolic links.
+fn normalize(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                res.pop();
+            }
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Finds the root of the workspace of the crate whose manifest is in `manifest_dir`, or gives `manifest_dir`
+/// if this crate is not part of a workspace.
+///
+/// Like cargo, it uses the `package.workspace` key of the manifest if there is one, or the first parent directory
+/// that has a `[workspace]` manifest, if this crate is one of its members.
+pub fn find_root(manifest_dir: &Path) -> Result<PathBuf, String> {
+    let manifest = read_manifest(&manifest_dir.join("Cargo.toml"))?;
+    if manifest.get("workspace").is_some() {
+        return Ok(manifest_dir.to_path_buf());
+    }
+    if let Some(root) = manifest
+        .get("package")
+        .and_then(|p| p.get("workspace"))
+        .and_then(|w| w.as_str())
+    {
+        return Ok(normalize(&manifest_dir.join(root)));
+    }
+
+    for dir in manifest_dir.ancestors().skip(1) {
+        let path = dir.join("Cargo.toml");
+        if !path.is_file() {
+            continue;
+        }
+        let root_manifest = read_manifest(&path)?;
+        if root_manifest.get("workspace").is_none() {
+            continue;
+        }
+        let relative = manifest_dir.strip_prefix(dir).unwrap_or(manifest_dir);
+        return Ok(if is_member(&root_manifest, dir, relative) {
+            dir.to_path_buf()
+        } else {
+            manifest_dir.to_path_buf()
+        });
+    }
+    Ok(manifest_dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_manifest(root: &Path, dir: &str, content: &str) -> PathBuf {
+        let dir = root.join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), content).unwrap();
+        dir
+    }
+
+    #[test]
+    fn wildcard_test() {
+        assert!(wildcard_match("*", "foo"));
+        assert!(wildcard_match("foo-*", "foo-bar"));
+        assert!(wildcard_match("f?o", "foo"));
+        assert!(!wildcard_match("foo-*", "bar-foo"));
+        assert!(member_match("crates/*", Path::new("crates/foo")));
+        assert!(member_match("./crates/foo/", Path::new("crates/foo")));
+        assert!(!member_match("crates/*", Path::new("crates/foo/bar")));
+    }
+
+    #[test]
+    fn find_root_test() {
+        let root =
+            std::env::temp_dir().join(format!("gettext-macros-workspace-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let package = "[package]\nname = \"a\"\n";
+        write_manifest(
+            &root,
+            "",
+            "[workspace]\nmembers = [\"crates/*\", \"app\"]\nexclude = [\"crates/excluded\"]\n",
+        );
+        let nested = write_manifest(&root, "crates/nested", package);
+        let app = write_manifest(&root, "app", package);
+        let excluded = write_manifest(&root, "crates/excluded", package);
+        let sibling = write_manifest(&root, "sibling", package);
+        let explicit = write_manifest(
+            &root,
+            "other/explicit",
+            "[package]\nname = \"a\"\nworkspace = \"../..\"\n",
+        );
+        let inner = write_manifest(&root, "inner", "[workspace]\n");
+        let inner_member = write_manifest(
+            &root,
+            "inner/member",
+            "[package]\nname = \"a\"\n[dependencies]\n",
+        );
+        let dependency = write_manifest(
+            &root,
+            "dep",
+            "[package]\nname = \"dep\"\n[workspace]\n[dependencies]\nlib = { path = \"./lib\" }\n",
+        );
+        let lib = write_manifest(&root, "dep/lib", package);
+
+        assert_eq!(find_root(&nested).unwrap(), root);
+        assert_eq!(find_root(&app).unwrap(), root);
+        assert_eq!(find_root(&excluded).unwrap(), excluded);
+        assert_eq!(find_root(&sibling).unwrap(), sibling);
+        assert_eq!(find_root(&explicit).unwrap(), root);
+        assert_eq!(find_root(&root).unwrap(), root);
+        // the closest workspace is used, even if the crate is not one of its members
+        assert_eq!(find_root(&inner_member).unwrap(), inner_member);
+        assert_eq!(find_root(&inner).unwrap(), inner);
+        assert_eq!(find_root(&lib).unwrap(), dependency);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
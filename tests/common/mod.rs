@@ -0,0 +1,32 @@
+//! Helpers for the tests that build the packages of `tests/fixtures`, to check what happens in a whole build
+//! (with several targets, or with errors).
+
+use std::process::{Command, Output};
+
+/// Runs cargo with `args` in a package of `tests/fixtures`, with the given environment variables.
+///
+/// All the fixtures share a target directory, so that their dependencies are only built once.
+pub fn cargo(fixture: &str, args: &[&str], vars: &[(&str, &str)]) -> Output {
+    let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/").to_owned()
+        + fixture
+        + "/Cargo.toml";
+    let mut command = Command::new(env!("CARGO"));
+    command
+        .args(args)
+        .args(["--offline", "--quiet", "--manifest-path", &manifest])
+        .env(
+            "CARGO_TARGET_DIR",
+            concat!(env!("CARGO_TARGET_TMPDIR"), "/fixtures"),
+        )
+        .env_remove("GETTEXT_MACROS_LANGS")
+        .env_remove("GETTEXT_MACROS_ROOT");
+    for (name, value) in vars {
+        command.env(name, value);
+    }
+    command.output().expect("Couldn't run cargo")
+}
+
+/// The errors (and warnings) of a cargo command.
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,25 @@
+//! Checks the catalogs compiled to Rust code, with `compiled = true`.
+
+use gettext_macros::*;
+
+init_i18n!("compiled_test", dir = "tests/compiled", compiled = true, fr);
+
+#[test]
+fn compiled() {
+    let catalogs = get_catalogs();
+    let fr = catalogs.get("fr").unwrap();
+    assert_eq!(i18n!(fr, "Hello"), "Bonjour");
+    assert_eq!(i18n!(fr, context = "Greeting", "Hello"), "Salut");
+    // the plural forms of the language are compiled from its .po file: 0 is singular in French
+    assert_eq!(i18n!(fr, "One file", "{} files"; 0), "0 fichier");
+    assert_eq!(i18n!(fr, "One file", "{} files"; 2), "2 fichiers");
+    // the messages that are not translated yet are kept as they are
+    assert_eq!(i18n!(fr, "Not translated"), "Not translated");
+    assert_eq!(i18n!(fr, "One new file", "{} new files"; 3), "3 new files");
+}
+
+compile_i18n!();
+
+fn get_catalogs() -> &'static gettext_utils::Catalogs<gettext_utils::StaticCatalog> {
+    include_i18n!()
+}
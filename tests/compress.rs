@@ -0,0 +1,12 @@
+//! Checks the catalogs embedded compressed, with `compress = true`.
+
+mod common;
+
+#[test]
+fn compress() {
+    let output = common::cargo("compress", &["build"], &[]);
+    assert!(output.status.success(), "{}", common::stderr(&output));
+    let output = common::cargo("compress", &["run"], &[]);
+    assert!(output.status.success(), "{}", common::stderr(&output));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Bonjour world\n");
+}
@@ -0,0 +1,24 @@
+//! Checks that the translations are written to the directory given with `dir = "..."`.
+
+use gettext_macros::*;
+
+init_i18n!("dir_test", dir = "target/dir_test", fr);
+
+#[test]
+fn dir() {
+    let fr = get_catalogs().get("fr").unwrap();
+    assert_eq!(i18n!(fr, "Written elsewhere"), "Written elsewhere");
+
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/target/dir_test/dir_test");
+    let pot = std::fs::read_to_string(format!("{}/dir_test.pot", dir)).unwrap();
+    assert!(pot.contains("msgid \"Written elsewhere\""));
+    assert!(std::path::Path::new(dir).join("fr.po").is_file());
+    assert!(!std::path::Path::new("po/dir_test").exists());
+}
+
+compile_i18n!();
+
+fn get_catalogs() -> &'static gettext_utils::Catalogs<gettext_utils::LazyCatalog<gettext::Catalog>>
+{
+    include_i18n!()
+}
@@ -0,0 +1,14 @@
+use gettext_macros::*;
+
+init_i18n!("compress", dir = "translations", compress = true, fr);
+
+fn main() {
+    let fr = catalogs().get("fr").unwrap();
+    println!("{}", i18n!(fr, "Hello {}"; "world"));
+}
+
+compile_i18n!();
+
+fn catalogs() -> &'static gettext_utils::Catalogs<gettext_utils::LazyCatalog<gettext::Catalog>> {
+    include_i18n!()
+}
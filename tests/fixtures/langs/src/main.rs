@@ -0,0 +1,10 @@
+use gettext_macros::*;
+
+init_i18n!("langs", fr, de, ja { embed = false });
+
+compile_i18n!();
+
+fn main() {
+    let catalogs = include_i18n!();
+    println!("{}", catalogs.languages().collect::<Vec<_>>().join(" "));
+}
@@ -0,0 +1,5 @@
+use gettext_macros::*;
+
+pub fn greeting(cat: &gettext::Catalog) -> String {
+    i18n!(cat, "Message from the library")
+}
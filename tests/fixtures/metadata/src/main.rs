@@ -0,0 +1,10 @@
+use gettext_macros::*;
+
+compile_i18n!();
+
+fn main() {
+    let catalogs = include_i18n!();
+    let cat = catalogs.get("fr").unwrap();
+    println!("{}", metadata::greeting(cat));
+    println!("{}", i18n!(cat, "Message from the binary"));
+}
@@ -0,0 +1,10 @@
+use gettext_macros::*;
+
+init_i18n!("after_compile", fr);
+
+compile_i18n!();
+
+fn main() {
+    let cat = gettext::Catalog::empty();
+    println!("{}", i18n!(cat, "Too late"));
+}
@@ -0,0 +1,8 @@
+use gettext_macros::*;
+
+fn main() {
+    let cat = gettext::Catalog::empty();
+    println!("{}", i18n!(cat, "Too early"));
+}
+
+init_i18n!("before_init", fr);
@@ -0,0 +1,10 @@
+use gettext_macros::*;
+
+init_i18n!("include_first", fr);
+
+fn main() {
+    let catalogs = include_i18n!();
+    println!("{}", i18n!(catalogs.get("fr").unwrap(), "Not compiled yet"));
+}
+
+compile_i18n!();
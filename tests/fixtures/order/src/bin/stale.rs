@@ -0,0 +1,12 @@
+use gettext_macros::*;
+
+#[cfg(not(feature = "late"))]
+init_i18n!("stale", fr);
+
+fn main() {
+    let cat = gettext::Catalog::empty();
+    println!("{}", i18n!(cat, "Hello"));
+}
+
+#[cfg(feature = "late")]
+init_i18n!("stale", fr);
@@ -0,0 +1,29 @@
+//! Checks that the languages embedded by `include_i18n!` can be chosen with `GETTEXT_MACROS_LANGS`.
+
+mod common;
+
+/// Builds the fixture with the given value of `GETTEXT_MACROS_LANGS`, and gives the languages it embeds.
+fn embedded(langs: Option<&str>) -> Result<String, String> {
+    let vars: Vec<_> = langs
+        .map(|langs| ("GETTEXT_MACROS_LANGS", langs))
+        .into_iter()
+        .collect();
+    let output = common::cargo("langs", &["build"], &vars);
+    if !output.status.success() {
+        return Err(common::stderr(&output));
+    }
+    let output = common::cargo("langs", &["run"], &vars);
+    assert!(output.status.success(), "{}", common::stderr(&output));
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[test]
+fn langs() {
+    // the languages with `embed = false` are left out by default
+    assert_eq!(embedded(None).unwrap(), "fr de");
+    assert_eq!(embedded(Some("ja, fr")).unwrap(), "fr ja");
+    assert_eq!(embedded(Some("")).unwrap(), "fr de");
+    assert!(embedded(Some("eo"))
+        .unwrap_err()
+        .contains("GETTEXT_MACROS_LANGS contains eo, that is not a language listed in init_i18n!"));
+}
@@ -1,6 +1,19 @@
+// the tests are kept as they were written, even where clippy suggests simpler code
+#![allow(clippy::useless_vec)]
+
 use gettext_macros::*;
 
-init_i18n!("test", fr, en, de, ja);
+init_i18n!(
+    "test",
+    fr,
+    en,
+    de,
+    ja { plural_forms = "nplurals=1; plural=0;" },
+    "pt-BR",
+    gsw { fallback = de },
+    eo { embed = false },
+);
+init_i18n!("test_emails", de, fr { fallback = r#as }, as);
 
 #[test]
 fn main() {
@@ -18,12 +31,93 @@ fn main() {
     i18n!(cat, context = "Test context", "Hello");
     i18n!(cat, context = "Test context (plural)", "Hello", "Plural"; 2);
     i18n!(cat, context = "Test context (format)", "Hello {}"; "world");
+    let files = ["a.txt", "b.txt"];
+    let c = i18n!(
+        cat,
+        "Delete this file?",
+        "Delete these files?",
+        count = files.len()
+    );
+    assert_eq!(c, "Delete these files?");
+    let d = i18n!(cat, "{0} has one file", "{0} has {1} files", count = files.len(); "Bob", files.len());
+    assert_eq!(d, "Bob has 2 files");
+    // the count and the format arguments are only evaluated once
+    let mut calls = 0;
+    let e = i18n!(cat, "One call", "{} calls", count = { calls += 1; 2u32 }; 2);
+    assert_eq!((e.as_str(), calls), ("2 calls", 1));
+    // `count` can be an expression too, when it isn't followed by `=`
+    let (one, count) = ("One item", "{} items");
+    assert_eq!(i18n!(cat, one, count; 2), "2 items");
+    let mut remaining = std::iter::once(1);
+    assert_eq!(
+        i18n!(cat, "One file left", "{} files left"; remaining.next().unwrap()),
+        "One file left"
+    );
     println!("{} {}", x, b);
     println!("{}", i18n!(cat, "Woohoo, it {}"; "works"));
     println!(i18n_domain!());
 
+    let catalogs = get_catalogs();
+    assert_eq!(
+        catalogs.languages().collect::<Vec<_>>(),
+        ["fr", "en", "de", "ja", "pt_BR", "gsw"]
+    );
+    assert!(std::ptr::eq(
+        catalogs.get("pt-BR").unwrap(),
+        catalogs.get("pt_BR").unwrap()
+    ));
+    assert_eq!(catalogs.default_language(), Some("fr"));
+    assert!(catalogs.get("de").is_some());
+    assert!(catalogs.get("eo").is_none());
+    assert!(std::fs::read_to_string("po/test/ja.po")
+        .unwrap()
+        .contains("\"Plural-Forms: nplurals=1; plural=0;\\n\""));
+    assert!(std::ptr::eq(catalogs, get_catalogs()));
+    let en = catalogs.get("en").unwrap();
+    assert_eq!(i18n!(en, "Hello"), "Hello");
+
+    let domains = get_domains();
+    assert_eq!(
+        domains.domains().collect::<Vec<_>>(),
+        ["test", "test_emails"]
+    );
+    assert!(domains.get("test_emails", "fr").is_some());
+    assert!(domains.get("test_emails", "ja").is_none());
+    // languages can be keywords
+    assert!(domains.get("test_emails", "as").is_some());
+    assert!(domains.get("test", "ja").is_some());
+    assert!(domains.get("other", "ja").is_none());
+    let cats = domains.for_language("de");
+    assert_eq!(i18n!(cats, domain = "test", "Hello"), "Hello");
+    assert_eq!(
+        i18n!(cats, domain = "test", context = "Test context", "Hello {}"; "domain"),
+        "Hello domain"
+    );
+    assert_eq!(
+        t!(domain = "test", "Message of a domain"),
+        "Message of a domain"
+    );
+    assert_eq!(
+        i18n!(cats, domain = "test_emails", "Welcome {}"; "Bob"),
+        "Welcome Bob"
+    );
+    assert_eq!(t!(domain = "test_emails", "Your account"), "Your account");
+
     let v = vec![1, 2, 3];
     i18n!(cat, "Test complex formatting {}, {}", "aaahh"; v[0], v[2]);
+
+    use std::fmt::Write;
+    let mut out = String::new();
+    i18n_write!(out, cat, "Written {}"; "message").unwrap();
+    i18n_write!(&mut out, cat, context = "Test context (write)", ", one item", ", {} items"; v.len()).unwrap();
+    assert_eq!(out, "Written message, 3 items");
+
+    let name = "lazy";
+    let lazy = i18n_lazy!(cat, "Hello {}, {}"; name, v[1]);
+    assert_eq!(format!("[{}]", lazy), "[Hello lazy, 2]");
+    let pattern = String::from("{} lazy files");
+    let lazy = i18n_lazy!(cat, "One lazy file", &pattern; v[2]);
+    assert_eq!(lazy.to_string(), "3 lazy files");
 }
 
 compile_i18n!();
@@ -31,3 +125,12 @@ compile_i18n!();
 fn get_i18n() -> gettext::Catalog {
     include_i18n!()[0].1.clone()
 }
+
+fn get_catalogs() -> &'static gettext_utils::Catalogs<gettext_utils::LazyCatalog<gettext::Catalog>>
+{
+    include_i18n!()
+}
+
+fn get_domains() -> &'static gettext_utils::Domains<gettext_utils::LazyCatalog<gettext::Catalog>> {
+    include_i18n!("test", "test_emails")
+}
@@ -0,0 +1,36 @@
+//! Checks that a package configured in `[package.metadata.gettext]` can use the macros in several targets.
+
+mod common;
+
+#[test]
+fn metadata() {
+    let po = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/metadata/po/metadata"
+    );
+    let _ = std::fs::remove_dir_all(po);
+    let output = common::cargo("metadata", &["clean", "--package", "metadata"], &[]);
+    assert!(output.status.success(), "{}", common::stderr(&output));
+    let configs = concat!(env!("CARGO_TARGET_TMPDIR"), "/fixtures/gettext_macros");
+    for entry in std::fs::read_dir(configs).into_iter().flatten().flatten() {
+        if entry.file_name().to_string_lossy().starts_with("metadata") {
+            std::fs::remove_file(entry.path()).unwrap();
+        }
+    }
+
+    let output = common::cargo("metadata", &["build"], &[]);
+    assert!(output.status.success(), "{}", common::stderr(&output));
+    let output = common::cargo("metadata", &["run"], &[]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Message from the library\nMessage from the binary\n"
+    );
+
+    // the library configures the translations: the binary doesn't start a new .pot file, and its messages are not added
+    let pot = std::fs::read_to_string(format!("{}/metadata.pot", po)).unwrap();
+    assert!(pot.contains("msgid \"Message from the library\""));
+    assert!(!pot.contains("Message from the binary"));
+    // but it can compile them
+    let fr = std::fs::read_to_string(format!("{}/fr.po", po)).unwrap();
+    assert!(fr.contains("msgid \"Message from the library\""));
+}
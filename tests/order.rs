@@ -0,0 +1,29 @@
+//! Checks that the macros report an error when they are called in the wrong order.
+
+mod common;
+
+fn build_error(bin: &str, features: &[&str]) -> String {
+    let features = features.join(",");
+    let output = common::cargo(
+        "order",
+        &["build", "--bin", bin, "--features", &features],
+        &[],
+    );
+    assert!(!output.status.success(), "{} should not compile", bin);
+    common::stderr(&output)
+}
+
+#[test]
+fn order() {
+    assert!(
+        build_error("before_init", &[]).contains("init_i18n! should be called before this macro")
+    );
+    assert!(build_error("after_compile", &[]).contains("this message comes after compile_i18n!"));
+    assert!(build_error("include_first", &[])
+        .contains("compile_i18n! should be called before include_i18n!"));
+
+    // the configuration written by a previous build is not used
+    let output = common::cargo("order", &["build", "--bin", "stale"], &[]);
+    assert!(output.status.success(), "{}", common::stderr(&output));
+    assert!(build_error("stale", &["late"]).contains("was written by a previous build"));
+}